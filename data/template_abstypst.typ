@@ -0,0 +1,11 @@
+#set page(paper: "a4")
+#set document(title: "Seminar")
+#set text(lang: "{{ LANG }}")
+
+#align(center)[
+  #text(size: 20pt)[Seminar]
+
+  {{ DATES }}
+]
+
+{{ ABSTRACTS }}
@@ -0,0 +1,56 @@
+#set page(width: auto, height: auto, margin: 1cm)
+#set text(size: 10pt, lang: "{{ LANG }}")
+
+// Timetable data, generated by Seri: `first-hour`, `last-hour`, `days` and `events`
+{{ CALENDAR }}
+
+// These set the width of a day and the height of an hour.
+#let day-width = 6cm
+#let hour-height = 1.6cm
+#let hours-width = 1.5cm
+#let header-height = 1cm
+
+#let colors = (
+  talk: red.lighten(60%),
+  meal: green.lighten(60%),
+  "break": green.lighten(60%),
+  fun: blue.lighten(60%),
+  transport: gray.lighten(80%),
+)
+
+#let hour-y(hour) = header-height + hour-height * (hour - first-hour)
+
+#box(
+  width: hours-width + day-width * days.len(),
+  height: hour-y(last-hour),
+  {
+    // Hour marks and horizontal dividers
+    for hour in range(first-hour, last-hour + 1) {
+      place(dx: 0cm, dy: hour-y(hour) - 0.5em, box(width: hours-width - 0.2cm, align(right)[#hour:00]))
+      place(dx: hours-width, dy: hour-y(hour), line(length: day-width * days.len()))
+    }
+    // Date headers and vertical dividers
+    for (i, day) in days.enumerate() {
+      place(dx: hours-width + day-width * i, dy: 0.3cm, box(width: day-width, align(center, strong(day))))
+    }
+    for i in range(days.len() + 1) {
+      place(dx: hours-width + day-width * i, dy: header-height - 0.2cm, line(angle: 90deg, length: hour-y(last-hour) - header-height + 0.2cm))
+    }
+    // Events
+    for e in events {
+      place(
+        dx: hours-width + day-width * e.day + 2pt,
+        dy: hour-y(e.start),
+        rect(
+          width: day-width - 4pt,
+          height: hour-height * e.duration,
+          fill: colors.at(e.kind, default: blue.lighten(80%)),
+          stroke: 0.8pt,
+          radius: 3pt,
+          inset: 3pt,
+          align(center + horizon, text(size: if e.kind == "break" { 7pt } else { 10pt }, e.body)),
+        ),
+      )
+    }
+  },
+)
@@ -0,0 +1,103 @@
+/**
+ * @file Tree-sitter grammar for Seri timetables
+ * @license ACSL
+ *
+ * Follows the specification in `spec/seri.md`. Seri is read line by line, so every token but the
+ * parts of settings spans a whole line, line break included: when several tokens match the same
+ * line, their precedence chooses the one the specification reads first. This is why a timetable
+ * is expected to end with a line break.
+ */
+
+/// <reference types="tree-sitter-cli/dsl" />
+// @ts-check
+
+/** Precedence of the lines read before anything else */
+const SPECIAL = 3;
+
+module.exports = grammar({
+  name: 'seri',
+
+  // Blanks and line breaks are meaningful
+  extras: () => [],
+
+  rules: {
+    timetable: $ => seq(
+      repeat($._outside),
+      optional($.event),
+      repeat(seq($.delimiter, repeat($._outside), optional($.event))),
+    ),
+
+    // Comments before the first setting are not part of the event
+    _outside: $ => choice($._empty_line, $.comment, $.block_comment),
+
+    event: $ => seq(
+      repeat1($.setting),
+      optional(seq($._empty_line, optional($.description))),
+    ),
+
+    setting: $ => seq(
+      optional($.key),
+      ':',
+      optional(/[ \t]+/),
+      optional($.value),
+      '\n',
+      repeat(choice($.continuation, $.comment, $.block_comment)),
+    ),
+
+    key: () => /([^:\\\n]|\\[^\n])+/,
+
+    value: () => choice(
+      /[^ \t\n"][^\n]*/,
+      seq(/"([^"\\]|\\(.|\n))*"/, optional(/[ \t]+/)),
+    ),
+
+    // An unescaped `:` would make it a setting
+    continuation: () => token(prec(1, /[ \t]+([^ \t\n:\\]|\\[^\n])([^\n:\\]|\\[^\n])*\n/)),
+
+    // A code block left open runs up to the end of the event
+    description: $ => choice(
+      seq(repeat1($._description_line), optional($._open_code_block)),
+      $._open_code_block,
+    ),
+
+    _description_line: $ => choice($.text, $.comment, $.block_comment, $._empty_line, $.code_block),
+
+    // Comments are not read in code blocks, so that they can hold `//` lines
+    code_block: $ => choice(
+      seq($._backtick_block, $._backtick_fence_end),
+      seq($._tilde_block, $._tilde_fence_end),
+    ),
+
+    _open_code_block: $ => alias(choice($._backtick_block, $._tilde_block), $.code_block),
+
+    _backtick_block: $ => seq($._backtick_fence, repeat($._code_line)),
+
+    _tilde_block: $ => seq($._tilde_fence, repeat($._code_line)),
+
+    _backtick_fence: () => token(prec(1, /[ \t]*```[^\n]*\n/)),
+
+    _backtick_fence_end: () => token(prec(2, /[ \t]*```+[ \t]*\n/)),
+
+    _tilde_fence: () => token(prec(1, /[ \t]*~~~[^\n]*\n/)),
+
+    _tilde_fence_end: () => token(prec(2, /[ \t]*~~~+[ \t]*\n/)),
+
+    _code_line: () => /[^\n]*\n/,
+
+    text: () => /[ \t]*[^ \t\n][^\n]*\n/,
+
+    delimiter: () => token(prec(SPECIAL, /[ \t]*---[ \t]*\n/)),
+
+    comment: () => token(prec(SPECIAL, /[ \t]*\/\/[^\n]*\n/)),
+
+    block_comment: $ => seq($._block_start, repeat($._block_line), $._block_end),
+
+    _block_start: () => token(prec(SPECIAL, /[ \t]*\/\*[ \t]*\n/)),
+
+    _block_line: () => /[^\n]*\n/,
+
+    _block_end: () => token(prec(SPECIAL, /[ \t]*\*\/[ \t]*\n/)),
+
+    _empty_line: () => /[ \t]*\n/,
+  },
+});
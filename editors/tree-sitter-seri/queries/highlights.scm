@@ -0,0 +1,7 @@
+(key) @property
+(value) @string
+(continuation) @string
+(comment) @comment
+(block_comment) @comment
+(delimiter) @punctuation.delimiter
+":" @punctuation.delimiter
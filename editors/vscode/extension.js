@@ -0,0 +1,22 @@
+// Starts the Seri language server for `.seri` files
+const vscode = require("vscode");
+const { LanguageClient } = require("vscode-languageclient/node");
+
+let client;
+
+function activate() {
+  const command = vscode.workspace.getConfiguration("seri").get("server.path");
+  client = new LanguageClient(
+    "seri",
+    "Seri",
+    { command },
+    { documentSelector: [{ scheme: "file", language: "seri" }] }
+  );
+  client.start();
+}
+
+function deactivate() {
+  return client ? client.stop() : undefined;
+}
+
+module.exports = { activate, deactivate };
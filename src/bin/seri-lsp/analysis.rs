@@ -0,0 +1,344 @@
+//! Language features computed from the text of a timetable, independently of the protocol
+
+use std::fmt::Write;
+
+use chrono::Duration;
+use lsp_types::{
+    CompletionItem, CompletionItemKind, Diagnostic, DiagnosticSeverity, DocumentSymbol,
+    Documentation, Hover, HoverContents, MarkupContent, MarkupKind, Position, Range, SymbolKind,
+    TextEdit,
+};
+
+use seri::{
+    event::{Event, ParsingError, KNOWN_KEYS},
+    language,
+    passes::{lint, CompilingPass},
+    syntax::{self, split_list, unescape, RawEvent, TokenKind},
+};
+
+/// Documentation of the settings known by the compiler
+const KEY_DOCUMENTATION: [(&str, &str); 8] = [
+    (
+        "type",
+        "Type of the event: `talk`, `meal`, `break`, `fun` or `transport`",
+    ),
+    ("title", "Title of the event"),
+    ("date", "Beginning of the event, as `%Y-%m-%d %H:%M`"),
+    ("duration", "Duration of the event, in minutes"),
+    (
+        "lang",
+        "Languages of the talk, the main one first, as ISO 639 codes such as `fr, en`",
+    ),
+    ("speakers", "Comma-separated list of speakers"),
+    (
+        "repeat",
+        "`daily until %Y-%m-%d`, `weekly until %Y-%m-%d` or a list of dates",
+    ),
+    ("tags", "Comma-separated list of tags"),
+];
+
+/// Types of events
+const TYPES: [&str; 5] = ["talk", "meal", "break", "fun", "transport"];
+
+/// Number of UTF-16 code units of a text, the unit of positions in the protocol
+fn utf16_len(text: &str) -> u32 {
+    u32::try_from(text.encode_utf16().count()).unwrap_or(u32::MAX)
+}
+
+/// Byte index of a position in a line given in UTF-16 code units
+fn byte_index(line: &str, character: u32) -> usize {
+    let mut units = 0;
+    for (index, c) in line.char_indices() {
+        if units >= character as usize {
+            return index;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+/// Range of the given lines, starting at 1, both included
+fn line_range(lines: &[&str], first: usize, last: usize) -> Range {
+    let line = |l: usize| u32::try_from(l.saturating_sub(1)).unwrap_or(u32::MAX);
+    let end = lines
+        .get(last.saturating_sub(1))
+        .map_or(0, |l| utf16_len(l));
+    Range::new(
+        Position::new(line(first), 0),
+        Position::new(line(last), end),
+    )
+}
+
+/// A diagnostic on a whole line
+fn diagnostic(
+    lines: &[&str],
+    line: usize,
+    severity: DiagnosticSeverity,
+    message: String,
+) -> Diagnostic {
+    Diagnostic {
+        range: line_range(lines, line, line),
+        severity: Some(severity),
+        source: Some("seri".to_owned()),
+        message,
+        ..Diagnostic::default()
+    }
+}
+
+/// The message of a parsing error, without its location
+fn error_message(error: &ParsingError, line: usize) -> String {
+    let message = error.to_string();
+    message
+        .strip_prefix(&format!("line {line}: "))
+        .unwrap_or(&message)
+        .to_owned()
+}
+
+/// Parsing errors and lints of a timetable
+pub fn diagnostics(text: &str) -> Vec<Diagnostic> {
+    let lines: Vec<&str> = text.lines().collect();
+    let raw_events = match syntax::tokenize(text) {
+        Ok(tokens) => syntax::group(&tokens),
+        Err(e) => {
+            let line = e.line();
+            let message = error_message(&ParsingError::from(e), line);
+            return vec![diagnostic(&lines, line, DiagnosticSeverity::ERROR, message)];
+        }
+    };
+
+    let mut diagnostics = Vec::new();
+    let mut events = Vec::new();
+    for raw in &raw_events {
+        match Event::try_from(raw) {
+            Ok(event) => events.push((raw, event)),
+            Err(e) => {
+                let line = e.line().unwrap_or(raw.line);
+                let message = error_message(&e, line);
+                diagnostics.push(diagnostic(&lines, line, DiagnosticSeverity::ERROR, message));
+            }
+        }
+        if let Some(lang) = raw.get("lang") {
+            for code in split_list(&lang.value) {
+                if language::parse(&code).is_none() {
+                    diagnostics.push(diagnostic(
+                        &lines,
+                        lang.line,
+                        DiagnosticSeverity::WARNING,
+                        format!("`{code}` is not an ISO 639 language code"),
+                    ));
+                }
+            }
+        }
+    }
+
+    let (raws, events): (Vec<&RawEvent>, Vec<Event>) = events.into_iter().unzip();
+    let Ok(warnings) = lint::Pass::apply(&events);
+    for warning in warnings {
+        diagnostics.push(diagnostic(
+            &lines,
+            raws[warning.index].line,
+            DiagnosticSeverity::WARNING,
+            warning.message,
+        ));
+    }
+    diagnostics
+}
+
+/// Whether a line is in the header of its event, according to the lines before it
+fn in_header(lines: &[&str], line: usize) -> bool {
+    let is_comment = |l: &str| l.trim_start().starts_with("//");
+    let mut before = lines[..line.min(lines.len())].iter().rev();
+    while let Some(l) = before.next() {
+        if l.trim() == "---" {
+            return true;
+        }
+        if l.trim().is_empty() {
+            // An empty line ends the header, unless it is before the first setting
+            return before
+                .take_while(|l| l.trim() != "---")
+                .all(|l| l.trim().is_empty() || is_comment(l));
+        }
+    }
+    true
+}
+
+/// A completion item inserting a value
+fn value_item(label: &str, detail: Option<&str>) -> CompletionItem {
+    CompletionItem {
+        label: label.to_owned(),
+        kind: Some(CompletionItemKind::VALUE),
+        detail: detail.map(str::to_owned),
+        ..CompletionItem::default()
+    }
+}
+
+/// Completions of setting keys in headers, and of the values of `type`, `lang` and `repeat`
+pub fn completions(text: &str, position: Position) -> Vec<CompletionItem> {
+    let lines: Vec<&str> = text.lines().collect();
+    let line_index = position.line as usize;
+    let line = lines.get(line_index).copied().unwrap_or_default();
+    let before = &line[..byte_index(line, position.character)];
+    if !in_header(&lines, line_index) || before.starts_with([' ', '\t']) {
+        return Vec::new();
+    }
+
+    if let Some((key, _)) = before.split_once(':') {
+        return match key.trim() {
+            "type" => TYPES.iter().map(|t| value_item(t, None)).collect(),
+            "lang" => isolang::languages()
+                .filter_map(|l| Some(value_item(l.to_639_1()?, Some(l.to_name()))))
+                .collect(),
+            "repeat" => ["daily until ", "weekly until "]
+                .iter()
+                .map(|r| value_item(r, None))
+                .collect(),
+            _ => Vec::new(),
+        };
+    }
+
+    // The keys of the compiler, then the other keys of the timetable
+    let mut keys: Vec<(String, Option<&str>)> = KEY_DOCUMENTATION
+        .iter()
+        .map(|(key, documentation)| ((*key).to_owned(), Some(*documentation)))
+        .collect();
+    if let Ok(tokens) = syntax::tokenize(text) {
+        for token in tokens {
+            if let TokenKind::Pair { key, .. } = token.kind {
+                if !KNOWN_KEYS.contains(&key.as_str()) && !keys.iter().any(|(k, _)| *k == key) {
+                    keys.push((key, None));
+                }
+            }
+        }
+    }
+    keys.into_iter()
+        .map(|(key, documentation)| CompletionItem {
+            insert_text: Some(format!("{key}: ")),
+            label: key,
+            kind: Some(CompletionItemKind::PROPERTY),
+            documentation: documentation.map(|d| Documentation::String(d.to_owned())),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+/// The event at the given line, if it is valid
+fn event_at(text: &str, line: usize) -> Option<(RawEvent, Event)> {
+    let raw = syntax::group(&syntax::tokenize(text).ok()?)
+        .into_iter()
+        .find(|e| (e.line..=e.last_line).contains(&line))?;
+    let event = Event::try_from(&raw).ok()?;
+    Some((raw, event))
+}
+
+/// The day and the time span of the event under the cursor
+pub fn hover(text: &str, position: Position) -> Option<Hover> {
+    let lines: Vec<&str> = text.lines().collect();
+    let (raw, event) = event_at(text, position.line as usize + 1)?;
+    let end = event.start_date + Duration::minutes(i64::from(event.duration));
+    let end_format = if end.date_naive() == event.start_date.date_naive() {
+        "%H:%M"
+    } else {
+        "%A, %B %e %H:%M"
+    };
+    let mut value = format!(
+        "**{}**\n\n{} to {} ({} minutes)",
+        event.title,
+        event.start_date.format("%A, %B %e %Y, %H:%M"),
+        end.format(end_format),
+        event.duration
+    );
+    if let Some(recurrence) = &event.recurrence {
+        let _ = write!(value, "\n\nRepeats: {recurrence}");
+    }
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(line_range(&lines, raw.line, raw.last_line)),
+    })
+}
+
+/// One symbol per event, named after its title
+#[allow(deprecated)] // `DocumentSymbol::deprecated` has to be given
+pub fn symbols(text: &str) -> Vec<DocumentSymbol> {
+    let lines: Vec<&str> = text.lines().collect();
+    let Ok(tokens) = syntax::tokenize(text) else {
+        return Vec::new();
+    };
+    syntax::group(&tokens)
+        .iter()
+        .map(|raw| {
+            let title = raw.get("title");
+            DocumentSymbol {
+                name: title.map_or_else(|| "(no title)".to_owned(), |t| unescape(&t.value)),
+                detail: raw.get("date").map(|d| unescape(&d.value)),
+                kind: SymbolKind::EVENT,
+                tags: None,
+                deprecated: None,
+                range: line_range(&lines, raw.line, raw.last_line),
+                selection_range: {
+                    let line = title.map_or(raw.line, |t| t.line);
+                    line_range(&lines, line, line)
+                },
+                children: None,
+            }
+        })
+        .collect()
+}
+
+/// An edit replacing the whole text with its formatted version, if it can be tokenized and is
+/// not already formatted
+pub fn format(text: &str) -> Option<Vec<TextEdit>> {
+    let formatted = syntax::format(text).ok()?;
+    if formatted == text {
+        return Some(Vec::new());
+    }
+    // Up to the end of the text, including a last line without line break
+    let end = Position::new(u32::try_from(text.lines().count()).unwrap_or(u32::MAX), 0);
+    Some(vec![TextEdit::new(
+        Range::new(Position::new(0, 0), end),
+        formatted,
+    )])
+}
+
+#[test]
+fn test_analysis() {
+    let text = "title: Welcome\ndate: 2023-04-27 10:00\nduration: 30\nlang: xx\n\nHello\n---\ntitle: Talk\ntype: \ndate: 2023-04-27 10:15\nduration: 30\nspeakers: alice\n---\ntitle: Broken\ndate: tomorrow\nduration: 10\n";
+
+    let diagnostics: Vec<(u32, String)> = diagnostics(text)
+        .into_iter()
+        .map(|d| (d.range.start.line, d.message))
+        .collect();
+    assert!(diagnostics.contains(&(3, "`xx` is not an ISO 639 language code".to_owned())));
+    assert!(diagnostics.contains(&(0, "talk without speakers".to_owned())));
+    assert!(diagnostics
+        .iter()
+        .any(|(line, m)| *line == 14 && m.contains("tomorrow")));
+
+    let labels = |line, character| -> Vec<String> {
+        completions(text, Position::new(line, character))
+            .into_iter()
+            .map(|c| c.label)
+            .collect()
+    };
+    assert!(labels(8, 6).contains(&"transport".to_owned()));
+    assert!(labels(3, 6).contains(&"fr".to_owned()));
+    assert!(labels(7, 0).contains(&"speakers".to_owned()));
+    // Descriptions are not completed
+    assert!(labels(5, 0).is_empty());
+
+    let Some(Hover {
+        contents: HoverContents::Markup(content),
+        ..
+    }) = hover(text, Position::new(1, 0))
+    else {
+        panic!("no hover on a valid event");
+    };
+    assert!(content
+        .value
+        .contains("Thursday, April 27 2023, 10:00 to 10:30"));
+
+    let names: Vec<String> = symbols(text).into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["Welcome", "Talk", "Broken"]);
+}
@@ -0,0 +1,234 @@
+//! # `seri-lsp`
+//! Language server for Seri timetables, talking the Language Server Protocol on its standard
+//! input and output
+//!
+//! It reports parsing errors and lints as diagnostics, completes setting keys and the values of
+//! `type`, `lang` and `repeat`, shows the day and time span of events on hover, lists events as
+//! document symbols and formats timetables.
+
+// Make Clippy quite nasty
+#![deny(clippy::cargo)] // Checks for garbage in the Cargo TOML files
+#![allow(clippy::multiple_crate_versions)] // Dependencies doing bad things
+#![deny(clippy::complexity)] // Checks for needlessly complex structures
+#![deny(clippy::correctness)] // Checks for common invalid usage and workarounds
+#![deny(clippy::nursery)] // Checks for things that are typically forgotten by learners
+#![allow(clippy::option_if_let_else)] // Always suggests to use map_or_else instead of match, which
+// is hard to read
+#![deny(clippy::pedantic)] // Checks for mildly annoying comments it could make about your code
+#![deny(clippy::perf)] // Checks for inefficient ways to perform common tasks
+#![deny(clippy::style)] // Checks for inefficient styling of code
+#![deny(clippy::suspicious)] // Checks for potentially malicious behavior
+// Add some new Clippy lints
+#![deny(clippy::use_self)] // Checks for the use of a struct's name in its `impl`
+// Add some default lints
+#![warn(unused_variables)] // Checks for unused variables
+// Warn on missing documentation
+#![warn(missing_docs)]
+#![warn(rustdoc::missing_crate_level_docs)]
+
+use std::{collections::HashMap, process::ExitCode};
+
+use crossbeam_channel::SendError;
+use lsp_server::{Connection, ErrorCode, Message, Notification, ProtocolError, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as NotificationTrait, PublishDiagnostics,
+    },
+    request::{
+        Completion, DocumentSymbolRequest, Formatting, HoverRequest, Request as RequestTrait,
+    },
+    CompletionOptions, CompletionResponse, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentSymbolResponse, HoverProviderCapability, OneOf,
+    PublishDiagnosticsParams, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind,
+    Url,
+};
+use thiserror::Error;
+
+mod analysis;
+
+/// Error stopping the server
+#[derive(Debug, Error)]
+enum ServerError {
+    /// The client did not follow the protocol
+    #[error(transparent)]
+    Protocol(#[from] ProtocolError),
+    /// A message could not be sent to the client
+    #[error("could not send a message to the client: {0}")]
+    Send(#[from] SendError<Message>),
+    /// A message could not be encoded
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// The threads reading and writing messages failed
+    #[error("could not read or write messages: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Text of the open documents, by URI
+type Documents = HashMap<Url, String>;
+
+/// Features of the server
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![":".to_owned()]),
+            ..CompletionOptions::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// Send the diagnostics of a document
+fn publish_diagnostics(connection: &Connection, uri: Url, text: &str) -> Result<(), ServerError> {
+    let params = PublishDiagnosticsParams::new(uri, analysis::diagnostics(text), None);
+    connection
+        .sender
+        .send(Message::Notification(Notification::new(
+            PublishDiagnostics::METHOD.to_owned(),
+            params,
+        )))?;
+    Ok(())
+}
+
+/// Keep track of the open documents
+fn handle_notification(
+    connection: &Connection,
+    documents: &mut Documents,
+    notification: Notification,
+) -> Result<(), ServerError> {
+    match notification.method.as_str() {
+        DidOpenTextDocument::METHOD => {
+            let params: DidOpenTextDocumentParams = serde_json::from_value(notification.params)?;
+            let document = params.text_document;
+            publish_diagnostics(connection, document.uri.clone(), &document.text)?;
+            documents.insert(document.uri, document.text);
+        }
+        DidChangeTextDocument::METHOD => {
+            let params: DidChangeTextDocumentParams = serde_json::from_value(notification.params)?;
+            // Documents are synchronised in full, so the last change is the whole text
+            if let Some(change) = params.content_changes.into_iter().last() {
+                let uri = params.text_document.uri;
+                publish_diagnostics(connection, uri.clone(), &change.text)?;
+                documents.insert(uri, change.text);
+            }
+        }
+        DidCloseTextDocument::METHOD => {
+            let params: DidCloseTextDocumentParams = serde_json::from_value(notification.params)?;
+            documents.remove(&params.text_document.uri);
+            // Diagnostics of closed documents are not shown anymore
+            let params = PublishDiagnosticsParams::new(params.text_document.uri, Vec::new(), None);
+            connection
+                .sender
+                .send(Message::Notification(Notification::new(
+                    PublishDiagnostics::METHOD.to_owned(),
+                    params,
+                )))?;
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Answer a request with the result of a handler, given the text of the document
+fn respond<R: RequestTrait>(
+    request: Request,
+    documents: &Documents,
+    uri: impl Fn(&R::Params) -> &Url,
+    handler: impl Fn(&str, R::Params) -> R::Result,
+) -> Response {
+    match serde_json::from_value::<R::Params>(request.params) {
+        Ok(params) => match documents.get(uri(&params)) {
+            Some(text) => Response::new_ok(request.id, handler(text, params)),
+            None => Response::new_err(
+                request.id,
+                ErrorCode::InvalidParams as i32,
+                "unknown document".to_owned(),
+            ),
+        },
+        Err(e) => Response::new_err(request.id, ErrorCode::InvalidParams as i32, e.to_string()),
+    }
+}
+
+/// Answer the requests of the client
+fn handle_request(documents: &Documents, request: Request) -> Response {
+    match request.method.as_str() {
+        Completion::METHOD => respond::<Completion>(
+            request,
+            documents,
+            |p| &p.text_document_position.text_document.uri,
+            |text, p| {
+                Some(CompletionResponse::Array(analysis::completions(
+                    text,
+                    p.text_document_position.position,
+                )))
+            },
+        ),
+        HoverRequest::METHOD => respond::<HoverRequest>(
+            request,
+            documents,
+            |p| &p.text_document_position_params.text_document.uri,
+            |text, p| analysis::hover(text, p.text_document_position_params.position),
+        ),
+        DocumentSymbolRequest::METHOD => respond::<DocumentSymbolRequest>(
+            request,
+            documents,
+            |p| &p.text_document.uri,
+            |text, _| Some(DocumentSymbolResponse::Nested(analysis::symbols(text))),
+        ),
+        Formatting::METHOD => respond::<Formatting>(
+            request,
+            documents,
+            |p| &p.text_document.uri,
+            |text, _| analysis::format(text),
+        ),
+        _ => Response::new_err(
+            request.id,
+            ErrorCode::MethodNotFound as i32,
+            format!("unsupported request `{}`", request.method),
+        ),
+    }
+}
+
+/// Handle messages until the client shuts the server down
+fn main_loop(connection: &Connection) -> Result<(), ServerError> {
+    let mut documents = Documents::new();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                let response = handle_request(&documents, request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                handle_notification(connection, &mut documents, notification)?;
+            }
+            Message::Response(_) => (),
+        }
+    }
+    Ok(())
+}
+
+fn run() -> Result<(), ServerError> {
+    let (connection, io_threads) = Connection::stdio();
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+    main_loop(&connection)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,414 @@
+//! High-level API, reading a timetable and compiling it to an output format in one call
+
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{
+    event::{Event, ParsingError},
+    language::Flags,
+    locale::Locale,
+    passes::{
+        abstex, agenda, cache, csv, filter,
+        html::{HTMLBackend, HTMLBackendCompilationError, HTMLBackendOptions},
+        ics, latexmk,
+        parser::ParseTimetable,
+        pentabarf, plugin, recurrence, seri, svg, tikz, translate, typst, PassInput,
+    },
+};
+
+/// Format of a timetable given to [`read`] or [`compile`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    /// The Seri language
+    #[default]
+    Seri,
+    /// A spreadsheet, one event per row
+    Csv,
+    /// An iCalendar document
+    Ics,
+}
+
+impl InputFormat {
+    /// All the input formats
+    pub const ALL: [Self; 3] = [Self::Seri, Self::Csv, Self::Ics];
+
+    /// Name of the format, as written on the command line
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Seri => "seri",
+            Self::Csv => "csv",
+            Self::Ics => "ics",
+        }
+    }
+
+    /// Guess the input format from the extension of a file, in any case, defaulting to Seri
+    #[must_use]
+    pub fn from_path(path: &str) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("csv") => Self::Csv,
+            Some("ics" | "ical") => Self::Ics,
+            _ => Self::Seri,
+        }
+    }
+}
+
+/// Output format of [`compile`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// `LaTeX` document with a `TikZ` timetable
+    #[default]
+    Tikz,
+    /// PDF of the `TikZ` timetable
+    TikzPdf,
+    /// `LaTeX` abstracts booklet
+    AbstractLatex,
+    /// PDF of the `LaTeX` abstracts booklet
+    AbstractPdf,
+    /// HTML calendar
+    Html,
+    /// Pentabarf/Frab `schedule.xml`
+    Pentabarf,
+    /// Spreadsheet, one event per row
+    Csv,
+    /// The Seri language
+    Seri,
+    /// SVG timetable
+    Svg,
+    /// Typst timetable
+    Typst,
+    /// Typst abstracts booklet
+    AbstractTypst,
+    /// Markdown agenda
+    Markdown,
+    /// Plain-text agenda
+    Text,
+}
+
+impl Format {
+    /// All the output formats
+    pub const ALL: [Self; 13] = [
+        Self::Tikz,
+        Self::TikzPdf,
+        Self::AbstractLatex,
+        Self::AbstractPdf,
+        Self::Html,
+        Self::Pentabarf,
+        Self::Csv,
+        Self::Seri,
+        Self::Svg,
+        Self::Typst,
+        Self::AbstractTypst,
+        Self::Markdown,
+        Self::Text,
+    ];
+
+    /// Name of the format, as written on the command line
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Tikz => "tikz",
+            Self::TikzPdf => "tikz-pdf",
+            Self::AbstractLatex => "abstract-latex",
+            Self::AbstractPdf => "abstract-pdf",
+            Self::Html => "html",
+            Self::Pentabarf => "pentabarf",
+            Self::Csv => "csv",
+            Self::Seri => "seri",
+            Self::Svg => "svg",
+            Self::Typst => "typst",
+            Self::AbstractTypst => "abstract-typst",
+            Self::Markdown => "markdown",
+            Self::Text => "text",
+        }
+    }
+}
+
+/// The format name given by the user is not known.
+#[derive(Debug, Error)]
+#[error("`{0}` is not a known format")]
+pub struct UnknownFormat(pub String);
+
+impl FromStr for InputFormat {
+    type Err = UnknownFormat;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.name() == input)
+            .ok_or_else(|| UnknownFormat(input.to_owned()))
+    }
+}
+
+impl FromStr for Format {
+    type Err = UnknownFormat;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.name() == input)
+            .ok_or_else(|| UnknownFormat(input.to_owned()))
+    }
+}
+
+impl fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Options for [`read`] and [`compile`]
+#[derive(Default, Clone)]
+pub struct Options {
+    /// Format of the input
+    pub input_format: InputFormat,
+    /// Options for reading and writing CSV
+    pub csv: csv::Options,
+    /// Events to keep
+    pub filter: filter::Options,
+    /// Language of the titles and descriptions
+    pub translate: translate::Options,
+    /// Path to the template file of the backend. If not set, its default template will be used.
+    pub template_path: Option<String>,
+    /// Options for compiling `LaTeX` to PDF
+    pub latexmk: latexmk::Options,
+    /// Cache of the PDFs. If not set, they are always rebuilt.
+    pub cache: Option<cache::Options<()>>,
+    /// Options for the Markdown and text agendas
+    pub agenda: agenda::Options,
+    /// Options for the Pentabarf schedule
+    pub pentabarf: pentabarf::Options,
+    /// Language of the dates and labels written by the backends
+    pub locale: Locale,
+    /// Flags shown instead of the codes of the languages of the talks
+    pub flags: Flags,
+}
+
+/// Error occurring when reading or compiling a timetable.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The Seri input could not be parsed
+    #[error("could not parse the timetable: {0}")]
+    Parse(#[from] ParsingError),
+    /// The CSV input could not be read, or the CSV output written
+    #[error("could not process CSV: {0}")]
+    Csv(#[from] csv::Error),
+    /// The iCalendar input could not be read
+    #[error("could not read iCalendar: {0}")]
+    Ics(#[from] ics::Error),
+    /// The recurring events could not be expanded
+    #[error("could not expand recurring events: {0}")]
+    Recurrence(#[from] recurrence::Error),
+    /// The HTML backend failed
+    #[error("could not generate HTML: {0}")]
+    Html(#[from] HTMLBackendCompilationError),
+    /// The `LaTeX` abstracts backend failed
+    #[error("could not generate LaTeX abstracts: {0}")]
+    AbstractLatex(#[from] abstex::Error),
+    /// The `TikZ` backend failed
+    #[error("could not generate TikZ: {0}")]
+    Tikz(#[from] tikz::Error),
+    /// The SVG backend failed
+    #[error("could not generate SVG: {0}")]
+    Svg(#[from] svg::Error),
+    /// The Typst backends failed
+    #[error("could not generate Typst: {0}")]
+    Typst(#[from] typst::Error),
+    /// The `LaTeX` document could not be compiled to PDF
+    #[error("could not compile LaTeX: {0}")]
+    Latex(#[from] latexmk::Error),
+    /// The Pentabarf backend failed
+    #[error("could not generate the Pentabarf schedule: {0}")]
+    Pentabarf(#[from] pentabarf::Error),
+    /// A plugin failed
+    #[error("{0}")]
+    Plugin(#[from] plugin::Error),
+}
+
+/// Passes that cannot fail
+impl From<std::convert::Infallible> for Error {
+    fn from(never: std::convert::Infallible) -> Self {
+        match never {}
+    }
+}
+
+/// Read the events of a timetable, then expand recurring events (if `expand_recurrences` is set),
+/// filter them and translate them
+///
+/// # Errors
+///
+/// Returns an error if the input is not valid in its format.
+pub fn read(input: &str, options: &Options, expand_recurrences: bool) -> Result<Vec<Event>, Error> {
+    let mut events = match options.input_format {
+        InputFormat::Seri => input.chain_pass::<ParseTimetable>()?,
+        InputFormat::Csv => {
+            input.chain_pass_with::<csv::Import, csv::Options>(options.csv.clone())?
+        }
+        InputFormat::Ics => input.chain_pass::<ics::Import>()?,
+    };
+    if expand_recurrences {
+        events = events.chain_pass::<recurrence::Pass>()?;
+    }
+    let Ok(events) =
+        events.chain_pass_with::<filter::Pass, filter::Options>(options.filter.clone());
+    let Ok(events) =
+        events.chain_pass_with::<translate::Pass, translate::Options>(options.translate.clone());
+    Ok(events)
+}
+
+/// Compile `LaTeX` to PDF, through the cache if it is enabled
+pub(crate) fn compile_pdf(
+    latex: &str,
+    mut latexmk_options: latexmk::Options,
+    cache: Option<cache::Options<()>>,
+) -> Result<Vec<u8>, Error> {
+    let Some(cache) = cache else {
+        return Ok(latex.chain_pass_with::<latexmk::Pass, latexmk::Options>(latexmk_options)?);
+    };
+    // The PDF is copied here rather than by Latexmk, so that it is also done on cache hits
+    let output_path = latexmk_options.output_path.take();
+    let pdf = latex
+        .chain_pass_with::<cache::CachedRecorded<latexmk::Recording>, cache::Options<latexmk::Options>>(
+            cache.with_inner(latexmk_options),
+        )?;
+    if let Some(output) = output_path {
+        fs::write(output, &pdf).map_err(latexmk::Error::from)?;
+    }
+    Ok(pdf)
+}
+
+/// Write events to an output format
+///
+/// # Errors
+///
+/// Returns an error if the backend fails.
+pub fn generate(events: Vec<Event>, format: Format, options: Options) -> Result<Vec<u8>, Error> {
+    let template_path = options.template_path;
+    let locale = options.locale;
+    Ok(match format {
+        Format::Tikz => events
+            .chain_pass_with::<tikz::Pass, tikz::Options>(tikz::Options {
+                template_path,
+                locale,
+            })?
+            .into_bytes(),
+        Format::TikzPdf => {
+            let latex = events.chain_pass_with::<tikz::Pass, tikz::Options>(tikz::Options {
+                template_path,
+                locale,
+            })?;
+            compile_pdf(&latex, options.latexmk, options.cache)?
+        }
+        Format::AbstractLatex => events
+            .chain_pass_with::<abstex::Pass, abstex::Options>(abstex::Options {
+                template_path,
+                locale,
+                flags: options.flags,
+            })?
+            .into_bytes(),
+        Format::AbstractPdf => {
+            let latex =
+                events.chain_pass_with::<abstex::Pass, abstex::Options>(abstex::Options {
+                    template_path,
+                    locale,
+                    flags: options.flags,
+                })?;
+            compile_pdf(&latex, options.latexmk, options.cache)?
+        }
+        Format::Html => events
+            .chain_pass_with::<HTMLBackend, HTMLBackendOptions>(HTMLBackendOptions {
+                template_path,
+                locale,
+                flags: options.flags,
+            })?
+            .into_bytes(),
+        Format::Pentabarf => events
+            .chain_pass_with::<pentabarf::Pass, pentabarf::Options>(options.pentabarf)?
+            .into_bytes(),
+        Format::Csv => events
+            .chain_pass_with::<csv::Export, csv::Options>(options.csv)?
+            .into_bytes(),
+        Format::Seri => {
+            let Ok(seri) = events.chain_pass::<seri::Pass>();
+            seri.into_bytes()
+        }
+        Format::Svg => events
+            .chain_pass_with::<svg::Pass, svg::Options>(svg::Options {
+                template_path,
+                locale,
+            })?
+            .into_bytes(),
+        Format::Typst => events
+            .chain_pass_with::<typst::Timetable, typst::Options>(typst::Options {
+                template_path,
+                locale,
+            })?
+            .into_bytes(),
+        Format::AbstractTypst => events
+            .chain_pass_with::<typst::Abstracts, typst::Options>(typst::Options {
+                template_path,
+                locale,
+            })?
+            .into_bytes(),
+        Format::Markdown => {
+            let Ok(markdown) =
+                events.chain_pass_with::<agenda::Markdown, agenda::Options>(agenda::Options {
+                    locale,
+                    ..options.agenda
+                });
+            markdown.into_bytes()
+        }
+        Format::Text => {
+            let Ok(text) =
+                events.chain_pass_with::<agenda::Text, agenda::Options>(agenda::Options {
+                    locale,
+                    ..options.agenda
+                });
+            text.into_bytes()
+        }
+    })
+}
+
+/// Read a timetable and compile it to an output format
+///
+/// Recurring events are expanded, except when writing back to the Seri language.
+///
+/// # Errors
+///
+/// Returns an error if the input is not valid in its format, or if the backend fails.
+///
+/// # Example
+///
+/// ```rust
+/// let agenda = seri::compile(
+///     "title: Welcome\ndate: 2023-04-27 10:00\nduration: 15",
+///     seri::Format::Text,
+///     seri::Options::default(),
+/// )
+/// .unwrap();
+/// assert!(String::from_utf8(agenda).unwrap().contains("10:00-10:15  Welcome"));
+/// ```
+pub fn compile(input: &str, format: Format, options: Options) -> Result<Vec<u8>, Error> {
+    // Recurrences are written back as is in Seri
+    let events = read(input, &options, format != Format::Seri)?;
+    generate(events, format, options)
+}
+
+#[test]
+fn test_input_format_from_path() {
+    assert_eq!(InputFormat::from_path("talks.CSV"), InputFormat::Csv);
+    assert_eq!(InputFormat::from_path("agenda.Ics"), InputFormat::Ics);
+    assert_eq!(InputFormat::from_path("programme.seri"), InputFormat::Seri);
+    assert_eq!(InputFormat::from_path("programme"), InputFormat::Seri);
+}
@@ -0,0 +1,364 @@
+//! Project configuration, read from a `seri.toml` file
+//!
+//! The top-level settings are named after the command line options, and give their default
+//! values. `[templates]` gives the template of each output format, and each `[targets.NAME]` table
+//! is an output built by `seri build`, with settings taking precedence over the top-level ones.
+//! Options given on the command line take precedence over both. Relative paths are relative to
+//! the directory of the configuration file.
+//!
+//! ```toml
+//! input = "programme.seri"
+//! timezone = "Europe/Paris"
+//! locale = "fr"
+//! flags = ["fr=🇫🇷", "en=🇬🇧"]
+//! language = "fr"
+//! latex-engine = "xelatex"
+//! lint = true
+//!
+//! [templates]
+//! tikz-pdf = "templates/timetable.tex"
+//!
+//! [targets.timetable]
+//! format = "tikz-pdf"
+//! output = "build/timetable.pdf"
+//!
+//! [targets.agenda]
+//! format = "markdown"
+//! output = "build/agenda.md"
+//! filters = ["!type=break"]
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::Args;
+
+/// Name of the configuration file, looked up next to the input
+pub const FILE_NAME: &str = "seri.toml";
+
+/// Error occurring when reading the configuration.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The configuration file could not be read
+    #[error("could not read {}: {source}", .path.display())]
+    CouldNotRead {
+        /// The configuration file
+        path: PathBuf,
+        /// The underlying error
+        #[source]
+        source: io::Error,
+    },
+    /// The configuration file is not valid TOML, or has unknown settings
+    #[error("invalid configuration in {}: {source}", .path.display())]
+    Invalid {
+        /// The configuration file
+        path: PathBuf,
+        /// The underlying error
+        #[source]
+        source: toml::de::Error,
+    },
+    /// A setting does not have a valid value
+    #[error("invalid value for `{key}` in the configuration: {message}")]
+    InvalidValue {
+        /// The setting
+        key: &'static str,
+        /// Why the value is not valid
+        message: String,
+    },
+    /// A target has targets itself
+    #[error("target `{0}` cannot have targets")]
+    NestedTargets(String),
+    /// The target asked for is not in the configuration
+    #[error("no target named `{0}` in the configuration")]
+    UnknownTarget(String),
+}
+
+/// Settings of the configuration file, or of one of its targets
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Settings {
+    input: Option<PathBuf>,
+    format: Option<String>,
+    template: Option<PathBuf>,
+    output: Option<PathBuf>,
+    save_tmp: Option<bool>,
+    latex_engine: Option<String>,
+    latex_args: Vec<String>,
+    latex_passes: Option<u32>,
+    cache_dir: Option<PathBuf>,
+    no_cache: Option<bool>,
+    pipeline: Option<String>,
+    pipeline_file: Option<PathBuf>,
+    filters: Vec<String>,
+    input_format: Option<String>,
+    csv_columns: Vec<String>,
+    csv_delimiter: Option<char>,
+    width: Option<usize>,
+    abstracts: Option<bool>,
+    timezone: Option<String>,
+    locale: Option<String>,
+    flags: Vec<String>,
+    language: Option<String>,
+    lint: Option<bool>,
+    deny_warnings: Option<bool>,
+    /// Template of each output format, by format name
+    templates: BTreeMap<String, PathBuf>,
+    /// Outputs built by `seri build`, by name
+    targets: BTreeMap<String, Self>,
+}
+
+/// A configuration file
+#[derive(Debug)]
+pub struct Config {
+    /// The directory of the file, which relative paths are relative to
+    directory: PathBuf,
+    settings: Settings,
+}
+
+/// Parse the value of a setting
+fn parse<T>(key: &'static str, value: Option<&String>) -> Result<Option<T>, Error>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .map(|v| {
+            v.parse().map_err(|e| Error::InvalidValue {
+                key,
+                message: format!("{e}"),
+            })
+        })
+        .transpose()
+}
+
+/// Parse every value of a list setting
+fn parse_all<T>(key: &'static str, values: &[String]) -> Result<Vec<T>, Error>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    values
+        .iter()
+        .map(|v| Ok(parse(key, Some(v))?.expect("the value is given")))
+        .collect()
+}
+
+impl Config {
+    /// Read a configuration file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, or is not a valid configuration.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(path).map_err(|source| Error::CouldNotRead {
+            path: path.to_owned(),
+            source,
+        })?;
+        let settings: Settings = toml::from_str(&content).map_err(|source| Error::Invalid {
+            path: path.to_owned(),
+            source,
+        })?;
+        if let Some((name, _)) = settings.targets.iter().find(|(_, t)| !t.targets.is_empty()) {
+            return Err(Error::NestedTargets(name.clone()));
+        }
+        Ok(Self {
+            directory: path.parent().map(Path::to_owned).unwrap_or_default(),
+            settings,
+        })
+    }
+
+    /// Find the configuration file next to the input file, or in the current directory if the
+    /// input is read from the standard input
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but is not a valid configuration.
+    pub fn find(input: Option<&str>) -> Result<Option<Self>, Error> {
+        let directory = input
+            .and_then(|i| Path::new(i).parent())
+            .unwrap_or_else(|| Path::new(""));
+        let path = directory.join(FILE_NAME);
+        if path.is_file() {
+            Self::read(&path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The targets with the given names, or every target if no name is given
+    ///
+    /// A configuration without targets has a single unnamed target, built from its top-level
+    /// settings.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a name is not a target of the configuration.
+    pub fn targets(&self, names: &[String]) -> Result<Vec<(&str, Option<&Settings>)>, Error> {
+        if names.is_empty() {
+            if self.settings.targets.is_empty() {
+                return Ok(vec![("default", None)]);
+            }
+            return Ok(self
+                .settings
+                .targets
+                .iter()
+                .map(|(name, target)| (name.as_str(), Some(target)))
+                .collect());
+        }
+        names
+            .iter()
+            .map(|name| {
+                self.settings
+                    .targets
+                    .get_key_value(name)
+                    .map(|(name, target)| (name.as_str(), Some(target)))
+                    .ok_or_else(|| Error::UnknownTarget(name.clone()))
+            })
+            .collect()
+    }
+
+    /// Fill the options not given on the command line from the target, then from the top-level
+    /// settings
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a setting does not have a valid value.
+    pub fn apply(&self, args: &mut Args, target: Option<&Settings>) -> Result<(), Error> {
+        let layers: Vec<&Settings> = target.into_iter().chain([&self.settings]).collect();
+        for settings in &layers {
+            self.apply_settings(args, settings)?;
+        }
+        if args.template.is_none() {
+            let format = args.format.unwrap_or_default().name();
+            args.template = layers
+                .iter()
+                .find_map(|s| s.templates.get(format))
+                .map(|t| self.path(t));
+        }
+        Ok(())
+    }
+
+    /// Resolve a path relative to the configuration file, as a string
+    fn path(&self, path: &Path) -> String {
+        self.directory.join(path).to_string_lossy().into_owned()
+    }
+
+    /// Fill the options that are still not set from one layer of settings
+    fn apply_settings(&self, args: &mut Args, s: &Settings) -> Result<(), Error> {
+        let path = |p: &Option<PathBuf>| p.as_deref().map(|p| self.path(p));
+
+        args.file = args.file.take().or_else(|| path(&s.input));
+        // The format and the pipelines replace each other
+        if args.format.is_none() && args.pipeline.is_none() && args.pipeline_file.is_none() {
+            args.format = parse("format", s.format.as_ref())?;
+            args.pipeline = parse("pipeline", s.pipeline.as_ref())?;
+            args.pipeline_file = path(&s.pipeline_file);
+        }
+        args.template = args.template.take().or_else(|| path(&s.template));
+        args.output = args.output.take().or_else(|| path(&s.output));
+        args.save_tmp = args.save_tmp.or(s.save_tmp);
+        if args.latex_engine.is_none() {
+            args.latex_engine = parse("latex-engine", s.latex_engine.as_ref())?;
+        }
+        if args.latex_extra.is_empty() {
+            args.latex_extra.clone_from(&s.latex_args);
+        }
+        args.latex_passes = args.latex_passes.or(s.latex_passes);
+        args.cache_dir = args
+            .cache_dir
+            .take()
+            .or_else(|| s.cache_dir.as_deref().map(|d| self.directory.join(d)));
+        args.no_cache = args.no_cache.or(s.no_cache);
+        if args.filters.is_empty() {
+            args.filters = parse_all("filters", &s.filters)?;
+        }
+        if args.input_format.is_none() {
+            args.input_format = parse("input-format", s.input_format.as_ref())?;
+        }
+        if args.csv_columns.is_empty() {
+            args.csv_columns = parse_all("csv-columns", &s.csv_columns)?;
+        }
+        if args.csv_delimiter.is_none() {
+            args.csv_delimiter = s
+                .csv_delimiter
+                .map(|c| {
+                    crate::parse_delimiter(&c.to_string()).map_err(|message| Error::InvalidValue {
+                        key: "csv-delimiter",
+                        message,
+                    })
+                })
+                .transpose()?;
+        }
+        args.width = args.width.or(s.width);
+        args.abstracts = args.abstracts.or(s.abstracts);
+        args.timezone = args.timezone.take().or_else(|| s.timezone.clone());
+        if args.locale.is_none() {
+            args.locale = parse("locale", s.locale.as_ref())?;
+        }
+        if args.flags.is_empty() {
+            args.flags = parse_all("flags", &s.flags)?;
+        }
+        if args.language.is_none() {
+            args.language = s
+                .language
+                .as_deref()
+                .map(|l| {
+                    crate::parse_language(l).map_err(|message| Error::InvalidValue {
+                        key: "language",
+                        message,
+                    })
+                })
+                .transpose()?;
+        }
+        args.lint = args.lint.or(s.lint);
+        args.deny_warnings = args.deny_warnings.or(s.deny_warnings);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_config() {
+    use clap::Parser;
+
+    let config = Config {
+        directory: PathBuf::from("project"),
+        settings: toml::from_str(
+            "input = \"programme.seri\"\nformat = \"tikz\"\nwidth = 50\n\
+             [templates]\nhtml = \"page.html\"\n\
+             [targets.site]\nformat = \"html\"\noutput = \"build/index.html\"\n\
+             [targets.agenda]\npipeline = \"seri | text\"\nwidth = 60",
+        )
+        .unwrap(),
+    };
+
+    let mut args = Args::parse_from(["seri", "--width", "80"]);
+    let targets = config.targets(&["site".to_owned()]).unwrap();
+    config.apply(&mut args, targets[0].1).unwrap();
+    assert_eq!(args.file.as_deref(), Some("project/programme.seri"));
+    assert_eq!(args.format, Some(seri::Format::Html));
+    assert_eq!(args.template.as_deref(), Some("project/page.html"));
+    assert_eq!(args.output.as_deref(), Some("project/build/index.html"));
+    assert_eq!(args.width, Some(80));
+
+    // The pipeline of the target replaces the top-level format
+    let mut args = Args::parse_from(["seri"]);
+    config
+        .apply(&mut args, config.settings.targets.get("agenda"))
+        .unwrap();
+    assert_eq!(args.format, None);
+    assert!(args.pipeline.is_some());
+    assert_eq!(args.width, Some(60));
+
+    assert!(matches!(
+        config.targets(&["nope".to_owned()]),
+        Err(Error::UnknownTarget(_))
+    ));
+}
@@ -1,15 +1,20 @@
 //! Specification of a timetable event
 
+use crate::language;
+use crate::locale::Locale;
+use crate::syntax::{self, split_list, unescape, RawEvent};
 use chrono::prelude::*;
 use chrono::{DateTime, Duration, Local};
 use isolang::Language;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::str::FromStr;
 use thiserror::Error;
 
 /// The type of a timetable event
-#[derive(Debug, PartialEq, Eq, Default, Clone)]
+#[derive(Debug, PartialEq, Eq, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Type {
     /// A Talk by someone
     #[default]
@@ -57,22 +62,243 @@ impl fmt::Display for Type {
 }
 
 /// A timetable event
-#[derive(Debug, Clone)]
+///
+/// Its JSON form, given to plugins, names the fields after the settings of the Seri language.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Event {
     /// The type of the event
+    #[serde(rename = "type")]
     pub event_type: Type,
-    /// The title of the event
+    /// The title of the event, empty if it has none
+    #[serde(default)]
     pub title: String,
     /// The beginning of the event
+    #[serde(rename = "date")]
     pub start_date: DateTime<Local>,
     /// The duration of the event (in minutes)
     pub duration: u32,
     /// The event description
+    #[serde(default)]
     pub description: Option<String>,
-    /// The language of the talk
-    pub language: Option<Language>,
+    /// Translations of the title, by language
+    #[serde(default)]
+    pub titles: BTreeMap<Language, String>,
+    /// Translations of the description, by language
+    #[serde(default)]
+    pub descriptions: BTreeMap<Language, String>,
+    /// The languages of the talk, the main one first
+    #[serde(default, rename = "lang")]
+    pub languages: Vec<Language>,
     /// The list of declared speakers
+    #[serde(default)]
     pub speakers: Vec<String>,
+    /// How the event repeats itself, if it does
+    #[serde(default, rename = "repeat")]
+    pub recurrence: Option<Recurrence>,
+    /// Free-form tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Every setting that the compiler does not know about, by key
+    #[serde(default)]
+    pub extra: BTreeMap<String, String>,
+}
+
+/// Settings with a dedicated field in [`Event`], that are not kept in [`Event::extra`]
+pub const KNOWN_KEYS: [&str; 8] = [
+    "type", "title", "date", "duration", "lang", "speakers", "repeat", "tags",
+];
+
+/// How an event repeats itself over several days
+///
+/// Serialized as its Seri form, such as `weekly until 2023-05-31`.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Recurrence {
+    /// Every day, up to the given date (included)
+    Daily {
+        /// The last day the event can happen
+        until: NaiveDate,
+    },
+    /// Every week on the same weekday, up to the given date (included)
+    Weekly {
+        /// The last day the event can happen
+        until: NaiveDate,
+    },
+    /// On each of the given days, in addition to the original date
+    Dates(Vec<NaiveDate>),
+}
+
+/// The recurrence rule provided is not valid.
+#[derive(Debug, Error)]
+#[error("`{0}` is not a valid recurrence, expected `daily until %Y-%m-%d`, `weekly until %Y-%m-%d` or a list of `%Y-%m-%d` dates")]
+pub struct InvalidRecurrence(pub String);
+
+impl FromStr for Recurrence {
+    type Err = InvalidRecurrence;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidRecurrence(input.to_owned());
+        let parse_date =
+            |date: &str| NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").map_err(|_| invalid());
+
+        let mut words = input.split_whitespace();
+        match (words.next(), words.next(), words.next(), words.next()) {
+            (Some(frequency), Some(keyword), Some(until), None)
+                if keyword.eq_ignore_ascii_case("until") =>
+            {
+                match frequency.to_lowercase().as_str() {
+                    "daily" => Ok(Self::Daily {
+                        until: parse_date(until)?,
+                    }),
+                    "weekly" => Ok(Self::Weekly {
+                        until: parse_date(until)?,
+                    }),
+                    _ => Err(invalid()),
+                }
+            }
+            _ => input
+                .replace(['[', ']'], "")
+                .split(',')
+                .filter(|s| !s.trim().is_empty())
+                .map(parse_date)
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|dates| {
+                    if dates.is_empty() {
+                        Err(invalid())
+                    } else {
+                        Ok(Self::Dates(dates))
+                    }
+                }),
+        }
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Daily { until } => write!(f, "daily until {}", until.format("%Y-%m-%d")),
+            Self::Weekly { until } => write!(f, "weekly until {}", until.format("%Y-%m-%d")),
+            Self::Dates(dates) => write!(
+                f,
+                "{}",
+                dates
+                    .iter()
+                    .map(|d| d.format("%Y-%m-%d").to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl TryFrom<String> for Recurrence {
+    type Error = InvalidRecurrence;
+
+    fn try_from(input: String) -> Result<Self, Self::Error> {
+        input.parse()
+    }
+}
+
+impl From<Recurrence> for String {
+    fn from(recurrence: Recurrence) -> Self {
+        recurrence.to_string()
+    }
+}
+
+impl Recurrence {
+    /// List the days on which an event starting on `first` happens, `first` included
+    #[must_use]
+    pub fn occurrences(&self, first: NaiveDate) -> Vec<NaiveDate> {
+        let mut days = vec![first];
+        match self {
+            Self::Daily { until } | Self::Weekly { until } => {
+                let step = if matches!(self, Self::Daily { .. }) {
+                    Duration::days(1)
+                } else {
+                    Duration::weeks(1)
+                };
+                let mut day = first + step;
+                while day <= *until {
+                    days.push(day);
+                    day += step;
+                }
+            }
+            Self::Dates(dates) => {
+                for d in dates {
+                    if !days.contains(d) {
+                        days.push(*d);
+                    }
+                }
+            }
+        }
+        days
+    }
+}
+
+/// The language of a translated title setting, such as `title.fr`
+fn title_variant(key: &str) -> Option<Language> {
+    key.strip_prefix("title.").and_then(language::parse)
+}
+
+/// A line starting a block of a description
+enum Tag {
+    /// `:::` alone, going back to the original description
+    Original,
+    /// A language, such as `::: fr`, starting a translation
+    Translation(Language),
+}
+
+/// Read a line of a description as a tag, if it is one
+fn language_tag(line: &str) -> Option<Tag> {
+    let tag = line.trim().strip_prefix(":::")?.trim();
+    if tag.is_empty() {
+        Some(Tag::Original)
+    } else {
+        language::parse(tag).map(Tag::Translation)
+    }
+}
+
+/// Split a description into the original text and its translations, given in blocks starting
+/// with a language tag
+fn split_descriptions(text: &str) -> (Option<String>, BTreeMap<Language, String>) {
+    let mut original = Vec::new();
+    let mut translations: BTreeMap<Language, Vec<&str>> = BTreeMap::new();
+    let mut current = None;
+    for line in text.lines() {
+        match language_tag(line) {
+            Some(Tag::Original) => {
+                current = None;
+                continue;
+            }
+            Some(Tag::Translation(language)) => {
+                current = Some(language);
+                continue;
+            }
+            None => (),
+        }
+        // A backslash keeps a line that would be read as a tag
+        let line = match line.trim_start().strip_prefix('\\') {
+            Some(rest) if language_tag(rest).is_some() => rest,
+            _ => line,
+        };
+        match current {
+            Some(language) => translations.entry(language).or_default().push(line),
+            None => original.push(line),
+        }
+    }
+    // Each text is kept without surrounding blank lines, and dropped if it is blank
+    let join = |lines: &[&str]| {
+        let first = lines.iter().position(|l| !l.trim().is_empty())?;
+        let last = lines.iter().rposition(|l| !l.trim().is_empty())?;
+        Some(lines[first..=last].join("\n"))
+    };
+    (
+        join(&original),
+        translations
+            .into_iter()
+            .filter_map(|(language, lines)| Some((language, join(&lines)?)))
+            .collect(),
+    )
 }
 
 /// Cut a text to be at most `length` characters
@@ -91,10 +317,20 @@ fn cut_text(text: &str, length: usize) -> String {
 }
 
 impl Event {
+    /// The title, or a placeholder in the given locale if the event has none
+    #[must_use]
+    pub fn display_title(&self, locale: Locale) -> &str {
+        if self.title.is_empty() {
+            locale.no_title()
+        } else {
+            &self.title
+        }
+    }
+
     /// Generate a short version of the title, up to `length` characters
     #[must_use]
-    pub fn short_title(&self, length: usize) -> String {
-        cut_text(&self.title, length)
+    pub fn short_title(&self, length: usize, locale: Locale) -> String {
+        cut_text(self.display_title(locale), length)
     }
 
     /// Generate a long version of all the speakers
@@ -103,41 +339,73 @@ impl Event {
         self.speakers.join(", ")
     }
 
+    /// Get the value of a setting by its key, either a known one, a translated title such as
+    /// `title.fr`, or one from [`Event::extra`]. Lists (`lang`, `speakers`, `tags`) are joined with
+    /// commas.
+    #[must_use]
+    pub fn field(&self, key: &str) -> Option<String> {
+        match key {
+            "type" => Some(self.event_type.to_string()),
+            "title" => Some(self.title.clone()),
+            "date" => Some(self.start_date.format("%Y-%m-%d %H:%M").to_string()),
+            "duration" => Some(self.duration.to_string()),
+            "lang" => Some(
+                self.languages
+                    .iter()
+                    .map(|l| language::code(*l))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )
+            .filter(|l| !l.is_empty()),
+            "speakers" => Some(self.speakers_string()),
+            "tags" => Some(self.tags.join(", ")),
+            _ => title_variant(key).map_or_else(
+                || self.extra.get(key).cloned(),
+                |language| self.titles.get(&language).cloned(),
+            ),
+        }
+    }
+
+    /// The description followed by its translations, each in a block starting with its language
+    /// tag, as written in the Seri language
+    #[must_use]
+    pub fn tagged_description(&self) -> Option<String> {
+        let mut blocks = Vec::new();
+        if let Some(description) = &self.description {
+            // Lines that would be read as tags are escaped
+            let lines: Vec<String> = description
+                .lines()
+                .map(|line| match language_tag(line) {
+                    Some(_) => format!("\\{line}"),
+                    None => line.to_owned(),
+                })
+                .collect();
+            blocks.push(lines.join("\n"));
+        }
+        for (language, description) in &self.descriptions {
+            blocks.push(format!("::: {}\n{description}", language::code(*language)));
+        }
+        Some(blocks.join("\n\n")).filter(|d| !d.is_empty())
+    }
+
     /// Generate the text content of an event in the calendar.
     /// For now, if speakers of an event are given, will print the first one (eventually succeeded by
-    /// `et~al.` if there are more) or the title, eventually truncated to 25 characters
+    /// `et~al.` if there are more) or the title, eventually truncated to 25 characters. The words
+    /// joining speakers are in the given locale.
     #[must_use]
-    pub fn short_text(&self) -> String {
+    pub fn short_text(&self, locale: Locale) -> String {
         match self.event_type {
             Type::Talk => match self.speakers.len() {
-                0 => self.short_title(30),
+                0 => self.short_title(30, locale),
                 1 => self.speakers[0].clone(),
-                2 => format!("{} and {}", self.speakers[0], self.speakers[1]),
-                _ => format!("{} et~al.", self.speakers[0]),
+                2 => format!("{} {} {}", self.speakers[0], locale.and(), self.speakers[1]),
+                _ => format!("{} {}", self.speakers[0], locale.et_al()),
             },
-            _ => self.short_title(30),
+            _ => self.short_title(30, locale),
         }
     }
 }
 
-/// The line of configuration given by the user is not a valid "key:value" pair.
-#[derive(Debug, Error)]
-#[error("line `{0}` is not a valid field")]
-pub struct InvalidField(pub String);
-
-/// Split header (cf grammar)
-fn split_pairs(string: &str) -> Result<HashMap<&str, &str>, InvalidField> {
-    string
-        .split('\n')
-        .map(|s| {
-            s.find(':')
-                .ok_or_else(|| InvalidField(s.to_owned()))
-                .map(|pos| s.split_at(pos))
-        })
-        .map(|field| field.map(|(key, val)| (key, val[1..].trim())))
-        .collect()
-}
-
 /// The parsing of an event failed.
 #[derive(Debug, Error)]
 pub enum ParsingError {
@@ -164,90 +432,167 @@ pub enum ParsingError {
     #[error(transparent)]
     InvalidTalkType(#[from] InvalidTalkType),
 
-    /// a line of configuration given as input is not a valid "key:value" pair.
+    /// The input does not follow the syntax of the language.
     #[error(transparent)]
-    InvalidField(#[from] InvalidField),
+    Syntax(#[from] syntax::Error),
+
+    /// The input did not contain exactly one event.
+    #[error("expected a single event, found {0}")]
+    NotASingleEvent(usize),
+
+    /// An error in the event starting at the given line.
+    #[error("line {line}: {source}")]
+    AtLine {
+        /// The line the error is located at, starting at 1
+        line: usize,
+        /// the underlying error
+        #[source]
+        source: Box<Self>,
+    },
 
     /// the given date does not respect the expected format.
     #[error("the give date `{0}` does not respect the expected format: `%Y-%m-%d %H:%M`")]
     InvalidDateShape(String),
+
+    /// The recurrence rule provided by the user is not valid.
+    #[error(transparent)]
+    InvalidRecurrence(#[from] InvalidRecurrence),
 }
 
-impl FromStr for Event {
-    type Err = ParsingError;
+impl ParsingError {
+    /// Locate the error at the given line, unless it is already located
+    #[must_use]
+    pub fn at_line(self, line: usize) -> Self {
+        match self {
+            Self::AtLine { .. } | Self::Syntax(_) => self,
+            _ => Self::AtLine {
+                line,
+                source: Box::new(self),
+            },
+        }
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let trimmed = s.trim();
-        let split_result = trimmed.split_once("\n\n");
+    /// The error without its location, for inputs that locate errors otherwise
+    #[must_use]
+    pub fn without_line(self) -> Self {
+        match self {
+            Self::AtLine { source, .. } => *source,
+            _ => self,
+        }
+    }
 
-        let (header, description) = if let Some((first, second)) = split_result {
-            let text = second.trim();
-            (first.trim(), (!text.is_empty()).then(|| text.to_owned()))
-        } else {
-            (trimmed, None)
-        };
+    /// The line the error is located at, if known
+    #[must_use]
+    pub const fn line(&self) -> Option<usize> {
+        match self {
+            Self::AtLine { line, .. } => Some(*line),
+            Self::Syntax(e) => Some(e.line()),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<&RawEvent> for Event {
+    type Error = ParsingError;
 
-        let settings = split_pairs(header)?;
+    fn try_from(raw: &RawEvent) -> Result<Self, Self::Error> {
+        // Errors on a given setting are located at its line, other ones at the event start
+        let setting = |key: &str| raw.get(key);
+        let value = |key: &str| setting(key).map(|s| unescape(&s.value));
+        let located = |key: &str| {
+            let line = setting(key).map_or(raw.line, |s| s.line);
+            move |e: ParsingError| e.at_line(line)
+        };
 
-        let event_type = settings
-            .get("type")
-            .as_ref()
+        let event_type = value("type")
             .map_or(Ok(Type::Talk), |talk_type| {
-                Type::from_str(talk_type).map_err(ParsingError::from)
-            })?;
+                Type::from_str(&talk_type).map_err(ParsingError::from)
+            })
+            .map_err(located("type"))?;
 
-        let language = settings.get("lang").and_then(|l| Language::from_639_1(l));
+        let languages = setting("lang").map_or_else(Vec::new, |s| {
+            split_list(&s.value)
+                .iter()
+                .filter_map(|l| language::parse(l))
+                .collect()
+        });
 
-        let title = settings.get("title").map_or("(no title)", |&e| e);
+        let title = value("title").unwrap_or_default();
 
         let date_name = String::from("date");
-        let start_date = settings
-            .get(date_name.as_str())
-            .ok_or(ParsingError::SettingNotFound { name: date_name })
+        let start_date = value(&date_name)
+            .ok_or_else(|| ParsingError::SettingNotFound {
+                name: date_name.clone(),
+            })
             .and_then(|datetime| {
                 Local
-                    .datetime_from_str(datetime, "%Y-%m-%d %H:%M")
-                    .map_err(|_| ParsingError::InvalidDateShape((*datetime).to_string()))
-            })?;
+                    .datetime_from_str(&datetime, "%Y-%m-%d %H:%M")
+                    .map_err(|_| ParsingError::InvalidDateShape(datetime))
+            })
+            .map_err(located(&date_name))?;
 
         let duration_name = String::from("duration");
-        let duration = settings
-            .get(duration_name.as_str())
-            .ok_or(ParsingError::SettingNotFound {
-                name: duration_name,
+        let duration = value(&duration_name)
+            .ok_or_else(|| ParsingError::SettingNotFound {
+                name: duration_name.clone(),
             })
             .and_then(|duration_setting| {
                 duration_setting
                     .parse()
                     .map_err(|err| ParsingError::CouldNotParseDuration { source: err })
-            })?;
-        let speakers = settings.get("speakers").map_or_else(Vec::new, |l| {
-            l.replace(['[', ']'], "")
-                .split(',')
-                .map(|s| s.trim().to_owned())
-                .filter(|s| !s.is_empty())
-                .collect()
-        });
-
-        let mut nonempty_description: Option<String> = description.map(|d| d.trim().into());
-        if let Some(d) = &nonempty_description {
-            if d.is_empty() {
-                nonempty_description = None;
+            })
+            .map_err(located(&duration_name))?;
+        let speakers = setting("speakers").map_or_else(Vec::new, |s| split_list(&s.value));
+        let tags = setting("tags").map_or_else(Vec::new, |s| split_list(&s.value));
+        let mut titles = BTreeMap::new();
+        let mut extra = BTreeMap::new();
+        for s in &raw.settings {
+            if let Some(language) = title_variant(&s.key) {
+                titles.insert(language, unescape(&s.value));
+            } else if !KNOWN_KEYS.contains(&s.key.as_str()) {
+                extra.insert(s.key.clone(), unescape(&s.value));
             }
         }
+        let (description, descriptions) = raw
+            .description
+            .as_deref()
+            .map_or_else(|| (None, BTreeMap::new()), split_descriptions);
+
+        let recurrence = value("repeat")
+            .map(|r| Recurrence::from_str(&r))
+            .transpose()
+            .map_err(ParsingError::from)
+            .map_err(located("repeat"))?;
 
         Ok(Self {
             event_type,
+            title,
             start_date,
             duration,
-            title: title.to_owned(),
-            description: nonempty_description,
-            language,
+            description,
+            titles,
+            descriptions,
+            languages,
             speakers,
+            recurrence,
+            tags,
+            extra,
         })
     }
 }
 
+impl FromStr for Event {
+    type Err = ParsingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let raw_events = syntax::group(&syntax::tokenize(s)?);
+        match raw_events.as_slice() {
+            [raw] => Self::try_from(raw),
+            _ => Err(ParsingError::NotASingleEvent(raw_events.len())),
+        }
+    }
+}
+
 /// Bounding box of event.
 ///
 /// This structure contains datetimes that allows to draw a box containing all the events from which
@@ -292,8 +637,8 @@ impl BoundingBox {
     /// Get how many days the event is lasting
     #[must_use]
     pub fn nb_days(&self) -> u32 {
-        let duration = self.down_right.day() - self.up_left.day();
-        duration + 1
+        let duration = (self.down_right.date_naive() - self.up_left.date_naive()).num_days();
+        u32::try_from(duration).unwrap_or_default() + 1
     }
 
     fn boundary(
@@ -308,10 +653,11 @@ impl BoundingBox {
         }
 
         if second.date_naive().cmp(&first.date_naive()) == order {
-            res = res
-                .with_day(second.day())?
-                .with_month(second.month())?
-                .with_year(second.year())?;
+            res = second
+                .date_naive()
+                .and_time(res.time())
+                .and_local_timezone(Local)
+                .single()?;
         }
 
         Some(res)
@@ -348,11 +694,10 @@ impl BoundingBox {
     }
 }
 
-/// TODO Do not assume everything is in the same month
 /// Will find the bounding box (date, times) to generate a timetable
 #[must_use]
 pub fn find_bounding_box(events: &Vec<Event>) -> Option<BoundingBox> {
-    let first = events.get(0)?;
+    let first = events.first()?;
     let mut up_left = first.start_date;
     let mut down_right = first.start_date;
 
@@ -369,6 +714,20 @@ pub fn find_bounding_box(events: &Vec<Event>) -> Option<BoundingBox> {
     })
 }
 
+/// The hour at which the last of the events has ended, counted from the midnight starting the day
+/// of each event, so that an event ending at midnight ends at 24:00 rather than 00:00
+#[must_use]
+pub fn end_hour(events: &[Event]) -> u32 {
+    events
+        .iter()
+        .map(|e| {
+            let start = e.start_date.hour() * 60 + e.start_date.minute();
+            start.saturating_add(e.duration).div_ceil(60)
+        })
+        .max()
+        .unwrap_or_default()
+}
+
 #[cfg(test)]
 fn create_empty_datetime() -> DateTime<Local> {
     NaiveDate::from_ymd_opt(0, 1, 1)
@@ -399,4 +758,31 @@ fn test_number_days() {
         up_left: create_empty_datetime(),
     };
     assert!(bb.nb_days() == 2);
+
+    // Test for events spanning on two months (31 January -> 2 February)
+    let bb = BoundingBox {
+        down_right: create_empty_datetime().with_month(2).unwrap(),
+        up_left: create_empty_datetime().with_day(31).unwrap(),
+    };
+    assert!(bb.nb_days() == 2);
+}
+
+#[test]
+fn test_recurrence_occurrences() {
+    let first = NaiveDate::from_ymd_opt(2023, 4, 27).unwrap();
+
+    let daily = Recurrence::from_str("daily until 2023-04-30").unwrap();
+    assert_eq!(daily.occurrences(first).len(), 4);
+
+    let weekly = Recurrence::from_str("weekly until 2023-05-10").unwrap();
+    assert_eq!(
+        weekly.occurrences(first),
+        vec![first, NaiveDate::from_ymd_opt(2023, 5, 4).unwrap()]
+    );
+
+    // The original date is not duplicated
+    let dates = Recurrence::from_str("2023-04-27, 2023-04-28").unwrap();
+    assert_eq!(dates.occurrences(first).len(), 2);
+
+    assert!(Recurrence::from_str("monthly until 2023-05-10").is_err());
 }
@@ -0,0 +1,141 @@
+//! Languages of the talks
+//!
+//! Talks give their languages as ISO 639 codes. Backends show each of them as a badge with its
+//! code, or as the flag given for it by the user, and set up hyphenation for the talks written in
+//! `LaTeX` and Typst.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use isolang::Language;
+use thiserror::Error;
+
+/// Read a language from its ISO 639-1 or ISO 639-3 code, in any case
+#[must_use]
+pub fn parse(code: &str) -> Option<Language> {
+    let code = code.trim().to_lowercase();
+    Language::from_639_1(&code).or_else(|| Language::from_639_3(&code))
+}
+
+/// The shortest ISO 639 code of a language: its ISO 639-1 code if it has one, its ISO 639-3 code
+/// otherwise
+#[must_use]
+pub fn code(language: Language) -> &'static str {
+    language.to_639_1().unwrap_or_else(|| language.to_639_3())
+}
+
+/// Name of a language for both `babel` and `polyglossia`, if they know it
+///
+/// Both packages are only given languages written in a script that `LaTeX` can typeset without
+/// extra fonts.
+#[must_use]
+pub fn latex_name(language: Language) -> Option<&'static str> {
+    const NAMES: [(&str, &str); 27] = [
+        ("ca", "catalan"),
+        ("cs", "czech"),
+        ("cy", "welsh"),
+        ("da", "danish"),
+        ("de", "german"),
+        ("en", "english"),
+        ("eo", "esperanto"),
+        ("es", "spanish"),
+        ("et", "estonian"),
+        ("eu", "basque"),
+        ("fi", "finnish"),
+        ("fr", "french"),
+        ("ga", "irish"),
+        ("gl", "galician"),
+        ("hr", "croatian"),
+        ("hu", "hungarian"),
+        ("is", "icelandic"),
+        ("it", "italian"),
+        ("la", "latin"),
+        ("lt", "lithuanian"),
+        ("lv", "latvian"),
+        ("nl", "dutch"),
+        ("pl", "polish"),
+        ("pt", "portuguese"),
+        ("ro", "romanian"),
+        ("sk", "slovak"),
+        ("sv", "swedish"),
+    ];
+    let code = language.to_639_1()?;
+    NAMES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, name)| *name)
+}
+
+/// A flag shown instead of the code of a language, given as `LANG=FLAG`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Flag {
+    /// The language
+    pub language: Language,
+    /// The text shown for it, usually a flag emoji
+    pub flag: String,
+}
+
+/// Error occurring when reading a flag
+#[derive(Debug, Error)]
+pub enum FlagError {
+    /// The flag is not given as `LANG=FLAG`
+    #[error("`{0}` should be written `LANG=FLAG`")]
+    Malformed(String),
+    /// The language is not an ISO 639 code
+    #[error("`{0}` is not an ISO 639 language code")]
+    UnknownLanguage(String),
+}
+
+impl FromStr for Flag {
+    type Err = FlagError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (language, flag) = input
+            .split_once('=')
+            .filter(|(_, flag)| !flag.trim().is_empty())
+            .ok_or_else(|| FlagError::Malformed(input.to_owned()))?;
+        Ok(Self {
+            language: parse(language)
+                .ok_or_else(|| FlagError::UnknownLanguage(language.trim().to_owned()))?,
+            flag: flag.trim().to_owned(),
+        })
+    }
+}
+
+/// The flags given by the user, by language
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Flags(BTreeMap<Language, String>);
+
+impl Flags {
+    /// The flag of a language, if one was given
+    #[must_use]
+    pub fn get(&self, language: Language) -> Option<&str> {
+        self.0.get(&language).map(String::as_str)
+    }
+}
+
+impl FromIterator<Flag> for Flags {
+    fn from_iter<I: IntoIterator<Item = Flag>>(flags: I) -> Self {
+        Self(flags.into_iter().map(|f| (f.language, f.flag)).collect())
+    }
+}
+
+#[test]
+fn test_language() {
+    assert_eq!(parse("FR"), Some(Language::Fra));
+    assert_eq!(parse("gsw"), Some(Language::Gsw));
+    assert_eq!(parse("xx"), None);
+    assert_eq!(code(Language::Fra), "fr");
+    assert_eq!(code(Language::Gsw), "gsw");
+    assert_eq!(latex_name(Language::Deu), Some("german"));
+    assert_eq!(latex_name(Language::Jpn), None);
+
+    let flags: Flags = ["fr=🇫🇷", "de = 🇩🇪"]
+        .iter()
+        .map(|f| f.parse::<Flag>().unwrap())
+        .collect();
+    assert_eq!(flags.get(Language::Fra), Some("🇫🇷"));
+    assert_eq!(flags.get(Language::Deu), Some("🇩🇪"));
+    assert_eq!(flags.get(Language::Eng), None);
+    assert!("fr".parse::<Flag>().is_err());
+    assert!("xx=🏴".parse::<Flag>().is_err());
+}
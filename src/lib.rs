@@ -0,0 +1,39 @@
+//! # `Seri`
+//! A Domain Specific Language Compiler for organizing events and exporting details to different formats
+//!
+//! The library reads timetables (from the Seri language, CSV or iCalendar) into
+//! [`event::Event`]s, and writes them through [`passes`] to the output formats. [`compile`] does
+//! both in one call.
+
+// Make Clippy quite nasty
+#![deny(clippy::cargo)] // Checks for garbage in the Cargo TOML files
+#![allow(clippy::multiple_crate_versions)] // Dependencies doing bad things
+#![deny(clippy::complexity)] // Checks for needlessly complex structures
+#![deny(clippy::correctness)] // Checks for common invalid usage and workarounds
+#![deny(clippy::nursery)] // Checks for things that are typically forgotten by learners
+#![allow(clippy::option_if_let_else)] // Always suggests to use map_or_else instead of match, which
+// is hard to read
+#![deny(clippy::pedantic)] // Checks for mildly annoying comments it could make about your code
+#![deny(clippy::perf)] // Checks for inefficient ways to perform common tasks
+#![deny(clippy::style)] // Checks for inefficient styling of code
+#![deny(clippy::suspicious)] // Checks for potentially malicious behavior
+// Add some new Clippy lints
+#![deny(clippy::use_self)] // Checks for the use of a struct's name in its `impl`
+// Add some default lints
+#![warn(unused_variables)] // Checks for unused variables
+// Warn on missing documentation
+#![warn(missing_docs)]
+#![warn(rustdoc::missing_crate_level_docs)]
+
+pub mod compiler;
+pub mod event;
+pub mod language;
+pub mod locale;
+pub mod markup;
+pub mod passes;
+pub mod pipeline;
+pub mod syntax;
+pub mod templating;
+
+pub use compiler::{compile, generate, read, Error, Format, InputFormat, Options};
+pub use locale::Locale;
@@ -0,0 +1,240 @@
+//! Languages of the generated documents
+//!
+//! Backends write dates and a few words, such as the "and" joining two speakers, in the language
+//! of the [`Locale`] they are given. Titles, descriptions and templates are left as written.
+
+use std::{fmt, str::FromStr};
+
+use chrono::{Datelike, Weekday};
+use isolang::Language;
+use thiserror::Error;
+
+/// Language of the dates and words written by the backends
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Locale {
+    /// English
+    #[default]
+    En,
+    /// French
+    Fr,
+    /// German
+    De,
+}
+
+/// The locale given by the user is not known.
+#[derive(Debug, Error)]
+#[error("`{0}` is not a known locale, expected one of `en`, `fr` or `de`")]
+pub struct UnknownLocale(pub String);
+
+impl Locale {
+    /// All the locales
+    pub const ALL: [Self; 3] = [Self::En, Self::Fr, Self::De];
+
+    /// ISO 639-1 code of the locale, as written on the command line
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::En => "en",
+            Self::Fr => "fr",
+            Self::De => "de",
+        }
+    }
+
+    /// The language of the locale
+    #[must_use]
+    pub const fn language(self) -> Language {
+        match self {
+            Self::En => Language::Eng,
+            Self::Fr => Language::Fra,
+            Self::De => Language::Deu,
+        }
+    }
+
+    /// Name of a day of the week
+    #[must_use]
+    pub const fn weekday(self, day: Weekday) -> &'static str {
+        let names = match self {
+            Self::En => [
+                "Monday",
+                "Tuesday",
+                "Wednesday",
+                "Thursday",
+                "Friday",
+                "Saturday",
+                "Sunday",
+            ],
+            Self::Fr => [
+                "lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche",
+            ],
+            Self::De => [
+                "Montag",
+                "Dienstag",
+                "Mittwoch",
+                "Donnerstag",
+                "Freitag",
+                "Samstag",
+                "Sonntag",
+            ],
+        };
+        names[day.num_days_from_monday() as usize]
+    }
+
+    /// Name of a month, starting at 1 for January
+    #[must_use]
+    pub const fn month(self, month: u32) -> &'static str {
+        let names = match self {
+            Self::En => [
+                "January",
+                "February",
+                "March",
+                "April",
+                "May",
+                "June",
+                "July",
+                "August",
+                "September",
+                "October",
+                "November",
+                "December",
+            ],
+            Self::Fr => [
+                "janvier",
+                "février",
+                "mars",
+                "avril",
+                "mai",
+                "juin",
+                "juillet",
+                "août",
+                "septembre",
+                "octobre",
+                "novembre",
+                "décembre",
+            ],
+            Self::De => [
+                "Januar",
+                "Februar",
+                "März",
+                "April",
+                "Mai",
+                "Juni",
+                "Juli",
+                "August",
+                "September",
+                "Oktober",
+                "November",
+                "Dezember",
+            ],
+        };
+        names[(month - 1) as usize % 12]
+    }
+
+    /// A day with its weekday and month, such as `Thursday, April 27` or `jeudi 27 avril`
+    #[must_use]
+    pub fn day(self, date: &impl Datelike) -> String {
+        let weekday = self.weekday(date.weekday());
+        let month = self.month(date.month());
+        let day = date.day();
+        match self {
+            Self::En => format!("{weekday}, {month} {day}"),
+            Self::Fr if day == 1 => format!("{weekday} 1er {month}"),
+            Self::Fr => format!("{weekday} {day} {month}"),
+            Self::De => format!("{weekday}, {day}. {month}"),
+        }
+    }
+
+    /// The days from `first` to `last`, or the single day if they are the same
+    #[must_use]
+    pub fn date_range(self, first: &impl Datelike, last: &impl Datelike) -> String {
+        let (from, to) = (self.day(first), self.day(last));
+        if from == to {
+            return from;
+        }
+        match self {
+            Self::En => format!("{from} to {to}"),
+            Self::Fr => format!("du {from} au {to}"),
+            Self::De => format!("{from} bis {to}"),
+        }
+    }
+
+    /// The word joining the last two items of a list
+    #[must_use]
+    pub const fn and(self) -> &'static str {
+        match self {
+            Self::En => "and",
+            Self::Fr => "et",
+            Self::De => "und",
+        }
+    }
+
+    /// The abbreviation following the first of many authors, with a `LaTeX` non-breaking space
+    #[must_use]
+    pub const fn et_al(self) -> &'static str {
+        match self {
+            Self::En | Self::Fr => "et~al.",
+            Self::De => "u.~a.",
+        }
+    }
+
+    /// Shown instead of the title of events without one
+    #[must_use]
+    pub const fn no_title(self) -> &'static str {
+        match self {
+            Self::En => "(no title)",
+            Self::Fr => "(sans titre)",
+            Self::De => "(ohne Titel)",
+        }
+    }
+
+    /// Tells readers of the HTML calendar how to show descriptions
+    #[must_use]
+    pub const fn show_descriptions(self) -> &'static str {
+        match self {
+            Self::En => "Click on the events to display their description",
+            Self::Fr => "Cliquez sur les événements pour afficher leur description",
+            Self::De => "Klicken Sie auf die Veranstaltungen, um ihre Beschreibung anzuzeigen",
+        }
+    }
+}
+
+impl FromStr for Locale {
+    type Err = UnknownLocale;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|l| l.name() == input)
+            .ok_or_else(|| UnknownLocale(input.to_owned()))
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[test]
+fn test_locale() {
+    use chrono::NaiveDate;
+
+    let thursday = NaiveDate::from_ymd_opt(2023, 4, 27).unwrap();
+    let saturday = NaiveDate::from_ymd_opt(2023, 4, 29).unwrap();
+    assert_eq!(Locale::En.day(&thursday), "Thursday, April 27");
+    assert_eq!(Locale::Fr.day(&thursday), "jeudi 27 avril");
+    assert_eq!(Locale::De.day(&thursday), "Donnerstag, 27. April");
+    assert_eq!(
+        Locale::Fr.day(&NaiveDate::from_ymd_opt(2023, 5, 1).unwrap()),
+        "lundi 1er mai"
+    );
+    assert_eq!(
+        Locale::Fr.date_range(&thursday, &saturday),
+        "du jeudi 27 avril au samedi 29 avril"
+    );
+    assert_eq!(
+        Locale::En.date_range(&thursday, &thursday),
+        "Thursday, April 27"
+    );
+    assert_eq!("de".parse::<Locale>().unwrap(), Locale::De);
+    assert!("es".parse::<Locale>().is_err());
+}
@@ -1,5 +1,5 @@
 //! # `Seri`
-//! A Domain Specific Language Compiler for organizing events and exporting details to different formats
+//! Command line interface of the Seri compiler, see the `seri` library for the compiler itself
 
 // Make Clippy quite nasty
 #![deny(clippy::cargo)] // Checks for garbage in the Cargo TOML files
@@ -21,65 +21,86 @@
 #![warn(missing_docs)]
 #![warn(rustdoc::missing_crate_level_docs)]
 
-use crate::{
-    event::{Event, ParsingError},
+use clap::{
+    builder::{PossibleValuesParser, TypedValueParser},
+    Parser, Subcommand,
+};
+
+use seri::{
+    language::{self, Flag},
     passes::{
-        abstex,
-        html::{HTMLBackend, HTMLBackendCompilationError, HTMLBackendOptions},
-        latexmk,
-        parser::ParseTimetable,
-        tikz, PassInput,
+        agenda, cache, csv, filter, latexmk,
+        lint::{self, Warning},
+        pentabarf, translate, CompilingPass,
     },
+    pipeline::{self, Pipeline, Registry},
+    Format, InputFormat, Locale, Options,
 };
 
-use clap::Parser;
+use isolang::Language;
 
 use std::{
     fs,
     io::{Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
 };
 
 use thiserror::Error;
 
-pub mod event;
-pub mod passes;
-pub mod templating;
+mod config;
 
-/// Help me to do something cleaner than this please
+use config::Config;
+
+/// Error occurring in the command line interface
 #[derive(Debug, Error)]
-pub enum CompilerError {
-    /// An error occurred in the Parser
-    #[error("Error while trying to parse Seri input: {0}")]
-    CouldNotParseSeri(#[from] ParsingError),
-    /// An error occurred in the HTML backend
-    #[error("Error while trying to generate the HTML output: {0}")]
-    CouldNotGenerateHTML(#[from] HTMLBackendCompilationError),
-    /// An error occurred in the LaTeX abstracts backend
-    #[error("Error while trying to generate the LaTeX abstract output: {0}")]
-    CouldNotGenerateAbsTex(#[from] abstex::Error),
-    /// An error occurred in the TikZ backend
-    #[error("Error while trying to generate the TikZ output: {0}")]
-    CouldNotGenerateTikz(#[from] tikz::Error),
-    /// An error occurred calling Latexmk
-    #[error("Error while trying to call Latexmk output: {0}")]
-    CouldNotCallLatexmk(#[from] latexmk::Error),
-    /// The output format selected is not supported
-    #[error("Backend not implemented yet: {0}")]
-    BackendNotImplemented(String),
-    /// An error occurred writing to stdout
-    #[error("Error while trying to write output: {0}")]
-    CouldNotReadUtf8(#[from] std::io::Error),
+enum CliError {
+    /// The timetable could not be compiled
+    #[error(transparent)]
+    Compile(#[from] seri::Error),
+    /// The input could not be read
+    #[error("Error while trying to read the input: {0}")]
+    CouldNotReadInput(std::io::Error),
+    /// The pipeline could not be run
+    #[error(transparent)]
+    Pipeline(#[from] pipeline::Error),
+    /// The output could not be written
+    #[error("Error while trying to write the output: {0}")]
+    CouldNotWriteOutput(std::io::Error),
+    /// The configuration file could not be read
+    #[error(transparent)]
+    Config(#[from] config::Error),
+    /// `seri build` was run without a configuration file
+    #[error("no {} found, needed by `seri build`", config::FILE_NAME)]
+    NoConfig,
+    /// Warnings were found while they are denied
+    #[error("{0} warning(s) found, denied by `--deny-warnings`")]
+    DeniedWarnings(usize),
+    /// The directory kept by `--save-tmp` could not be created
+    #[error(transparent)]
+    CouldNotCreateBuildDirectory(#[from] latexmk::TempFileCreationError),
 }
 
 /// Structure meant to store CLAP command line arguments
-#[derive(Parser, Debug)]
+///
+/// Every option can also be set in the configuration file, see the `config` module.
+#[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
+#[allow(clippy::struct_excessive_bools)] // Command line flags
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// An optional path to a file
     #[arg(help = "File to compile. If not present, will read from standard input")]
     file: Option<String>,
-    #[arg(short, long, value_enum, value_name = "FORMAT", help = "Output format", default_value_t=Format::Tikz)]
-    format: Format,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Configuration file [default: seri.toml next to the input]"
+    )]
+    config: Option<PathBuf>,
+    #[arg(short, long, value_name = "FORMAT", help = "Output format [default: tikz]", value_parser = format_parser())]
+    format: Option<Format>,
     #[arg(short, long, value_name = "TEMPLATE", help = "Template to use, if any")]
     template: Option<String>,
     #[arg(
@@ -89,69 +110,218 @@ struct Args {
         help = "Output file. If not present, will output to stdout"
     )]
     output: Option<String>,
-    #[arg(short, long, help = "Keep intermediate files", default_value_t = false)]
-    save_tmp: bool,
+    #[arg(short, long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true", help = "Keep intermediate files")]
+    save_tmp: Option<bool>,
+    #[arg(
+        long,
+        value_name = "ENGINE",
+        help = "LaTeX engine for PDF outputs: pdflatex, xelatex, lualatex or tectonic [default: lualatex]"
+    )]
+    latex_engine: Option<latexmk::Engine>,
+    #[arg(
+        long = "latex-args",
+        value_name = "ARG",
+        allow_hyphen_values = true,
+        help = "Extra argument given to latexmk, or to tectonic with that engine, can be repeated"
+    )]
+    latex_extra: Vec<String>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Maximum number of LaTeX compilation passes"
+    )]
+    latex_passes: Option<u32>,
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Directory where built PDFs are cached [default: ~/.cache/seri]"
+    )]
+    cache_dir: Option<PathBuf>,
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true", help = "Always rebuild PDFs")]
+    no_cache: Option<bool>,
+    #[arg(
+        long,
+        value_name = "PIPELINE",
+        conflicts_with = "format",
+        help = "Passes to run instead of the format, such as `seri | lint | tikz | latexmk`"
+    )]
+    pipeline: Option<Pipeline>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["format", "pipeline"],
+        help = "File holding the passes to run, one per line"
+    )]
+    pipeline_file: Option<String>,
+    #[arg(
+        long,
+        help = "List the passes usable in pipelines",
+        default_value_t = false
+    )]
+    list_passes: bool,
+    #[arg(
+        long = "filter",
+        value_name = "FILTER",
+        help = "Only keep events matching `tag:NAME`, `KEY=VALUE` or `KEY`, negated by a leading `!`"
+    )]
+    filters: Vec<filter::Predicate>,
+    #[arg(
+        short,
+        long,
+        value_name = "FORMAT",
+        value_parser = input_format_parser(),
+        help = "Input format. If not present, will be guessed from the file extension"
+    )]
+    input_format: Option<InputFormat>,
+    #[arg(
+        long = "csv-column",
+        value_name = "KEY=COLUMN",
+        help = "Name of the CSV column holding a setting, if not named after it"
+    )]
+    csv_columns: Vec<csv::ColumnMapping>,
+    #[arg(
+        long,
+        value_name = "CHAR",
+        value_parser = parse_delimiter,
+        help = "Field delimiter of CSV input and output [default: ,]"
+    )]
+    csv_delimiter: Option<u8>,
+    #[arg(
+        long,
+        value_name = "COLUMNS",
+        help = "Width at which the text agenda is wrapped [default: 72]"
+    )]
+    width: Option<usize>,
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true", help = "Show the abstracts in the Markdown and text agendas")]
+    abstracts: Option<bool>,
+    #[arg(
+        long,
+        value_name = "TZ",
+        help = "Time zone of the dates, such as `Europe/Paris` [default: the system time zone]"
+    )]
+    timezone: Option<String>,
+    #[arg(
+        long,
+        value_name = "LOCALE",
+        help = "Language of the dates and labels: en, fr or de [default: en]"
+    )]
+    locale: Option<Locale>,
+    #[arg(
+        long = "flag",
+        value_name = "LANG=FLAG",
+        help = "Flag shown instead of the code of a talk language, such as `fr=🇫🇷`"
+    )]
+    flags: Vec<Flag>,
+    #[arg(
+        long,
+        value_name = "LANG",
+        value_parser = parse_language,
+        help = "Language of the titles and descriptions, when events are translated [default: the original one]"
+    )]
+    language: Option<Language>,
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true", help = "Warn about likely mistakes in the timetable")]
+    lint: Option<bool>,
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true", help = "Fail when there are warnings")]
+    deny_warnings: Option<bool>,
 }
 
-#[derive(clap::ValueEnum, Clone, Debug)]
-#[allow(clippy::upper_case_acronyms)]
-enum Format {
-    Tikz,
-    TikzPDF,
-    AbstractLatex,
-    AbstractPDF,
-    HTML,
+/// Parse a single ASCII character used as a CSV field delimiter
+fn parse_delimiter(s: &str) -> Result<u8, String> {
+    match s.as_bytes() {
+        [c] if c.is_ascii() => Ok(*c),
+        _ => Err(format!("`{s}` is not a single ASCII character")),
+    }
 }
 
-impl PassInput for &str {}
-impl PassInput for Vec<Event> {}
+/// Parse an ISO 639-1 or ISO 639-3 language code
+fn parse_language(s: &str) -> Result<Language, String> {
+    language::parse(s).ok_or_else(|| format!("`{s}` is not an ISO 639 language code"))
+}
 
-fn generate_abstract_pdf(
-    content: &str,
-    abstex_options: abstex::Options,
-    latexmk_options: latexmk::Options,
-) -> Result<Vec<u8>, CompilerError> {
-    content
-        .chain_pass::<ParseTimetable>()?
-        .chain_pass_with::<abstex::Pass, abstex::Options>(abstex_options)?
-        .chain_pass_with::<latexmk::Pass, latexmk::Options>(latexmk_options)
-        .map_err(CompilerError::from)
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Convert a calendar or a spreadsheet to a Seri timetable
+    Import {
+        #[arg(help = "File to convert, its format is guessed from its extension")]
+        file: String,
+    },
+    /// Build the targets of the configuration file
+    Build {
+        #[arg(help = "Targets to build. If not present, will build all of them")]
+        targets: Vec<String>,
+    },
 }
 
-fn generate_tikz_pdf(
-    content: &str,
-    tikz_options: tikz::Options,
-    latexmk_options: latexmk::Options,
-) -> Result<Vec<u8>, CompilerError> {
-    content
-        .chain_pass::<ParseTimetable>()?
-        .chain_pass_with::<tikz::Pass, tikz::Options>(tikz_options)?
-        .chain_pass_with::<latexmk::Pass, latexmk::Options>(latexmk_options)
-        .map_err(CompilerError::from)
+/// Parse an output format name
+fn format_parser() -> impl TypedValueParser<Value = Format> {
+    PossibleValuesParser::new(Format::ALL.map(Format::name)).try_map(|name| name.parse::<Format>())
 }
 
-fn generate_tikz(options: tikz::Options, content: &str) -> Result<Vec<u8>, CompilerError> {
-    content
-        .chain_pass::<ParseTimetable>()?
-        .chain_pass_with::<tikz::Pass, tikz::Options>(options)
-        .map(String::into_bytes)
-        .map_err(CompilerError::from)
+/// Parse an input format name
+fn input_format_parser() -> impl TypedValueParser<Value = InputFormat> {
+    PossibleValuesParser::new(InputFormat::ALL.map(InputFormat::name))
+        .try_map(|name| name.parse::<InputFormat>())
 }
 
-fn generate_abstex(options: abstex::Options, content: &str) -> Result<Vec<u8>, CompilerError> {
-    content
-        .chain_pass::<ParseTimetable>()?
-        .chain_pass_with::<abstex::Pass, abstex::Options>(options)
-        .map(String::into_bytes)
-        .map_err(CompilerError::from)
+/// The cache directory of the user, such as `~/.cache/seri`
+fn default_cache_directory() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|cache| cache.join("seri"))
 }
 
-fn generate_html(options: HTMLBackendOptions, content: &str) -> Result<Vec<u8>, CompilerError> {
-    content
-        .chain_pass::<ParseTimetable>()?
-        .chain_pass_with::<HTMLBackend, HTMLBackendOptions>(options)
-        .map(String::into_bytes)
-        .map_err(CompilerError::from)
+/// Options of the PDF cache, if it is enabled
+fn cache_options(args: &Args) -> Option<cache::Options<()>> {
+    // The build directory is only kept when the PDF is actually built
+    if args.no_cache.unwrap_or(false) || args.save_tmp.unwrap_or(false) {
+        return None;
+    }
+    let directory = args.cache_dir.clone().or_else(default_cache_directory)?;
+    Some(cache::Options {
+        directory,
+        dependencies: args.template.iter().map(PathBuf::from).collect(),
+        max_age: cache::DEFAULT_MAX_AGE,
+        inner: (),
+    })
+}
+
+/// Gather the compiler options given on the command line
+fn options(args: Args, input_format: InputFormat) -> Options {
+    Options {
+        input_format,
+        cache: cache_options(&args),
+        csv: csv::Options {
+            columns: args.csv_columns,
+            delimiter: args.csv_delimiter,
+        },
+        filter: filter::Options {
+            predicates: args.filters,
+        },
+        translate: translate::Options {
+            language: args.language,
+        },
+        template_path: args.template,
+        latexmk: latexmk::Options {
+            input_path: None,
+            // The output is written below
+            output_path: None,
+            // Set once the input is known
+            build_directory: None,
+            source_directory: None,
+            engine: args.latex_engine.unwrap_or_default(),
+            extra_args: args.latex_extra,
+            passes: args.latex_passes,
+        },
+        agenda: agenda::Options {
+            width: args.width,
+            abstracts: args.abstracts.unwrap_or(false),
+            ..agenda::Options::default()
+        },
+        pentabarf: pentabarf::Options::default(),
+        locale: args.locale.unwrap_or_default(),
+        flags: args.flags.into_iter().collect(),
+    }
 }
 
 fn open_output_file(path: Option<String>) -> Result<Box<dyn Write>, std::io::Error> {
@@ -161,79 +331,190 @@ fn open_output_file(path: Option<String>) -> Result<Box<dyn Write>, std::io::Err
     }
 }
 
-/// Write the output to a file or to stdout
-///
-/// # Arguments
-///
-/// * `output` - The path to the output file. If `None`, will output to stdout
-/// * `data` - The data to write
-///
-/// # Returns
-///
-/// * `Ok(())` if the output was written successfully
-/// * `Err(std::io::Error)` if the output could not be written
-///
 fn write_output(output: &mut impl Write, data: &[u8]) -> Result<(), std::io::Error> {
     output.write_all(data)
 }
 
-fn main() -> Result<(), CompilerError> {
-    let args = Args::parse();
+/// Print the passes usable in pipelines
+fn list_passes(registry: &Registry) {
+    for (name, entry) in registry.entries() {
+        println!(
+            "{name:16}{:11}{:8}{}",
+            entry.kind.to_string(),
+            entry.input,
+            entry.description
+        );
+    }
+}
 
-    let template = args.template.clone();
-    let content = args.file.map_or_else(
-        || {
-            let mut buffer = Vec::new();
-            std::io::stdin().read_to_end(&mut buffer).unwrap();
-            String::from_utf8(buffer).unwrap()
-        },
-        |filepath| fs::read_to_string(filepath).expect("Could not read file"),
-    );
+/// Compile the events read from `content`, with `pipeline` if given, and gather the warnings
+fn compile(
+    pipeline: Option<Pipeline>,
+    registry: &Registry,
+    content: &str,
+    format: Format,
+    lint: bool,
+    options: Options,
+) -> Result<(Vec<u8>, Vec<Warning>), CliError> {
+    if let Some(pipeline) = pipeline {
+        let output = pipeline.run(registry, content, &options)?;
+        // Shown as the plugins wrote them
+        for message in &output.messages {
+            eprintln!("{message}");
+        }
+        return Ok((output.document, output.warnings));
+    }
+    // Recurrences are written back as is in Seri
+    let events = seri::read(content, &options, format != Format::Seri)?;
+    let warnings = if lint {
+        let Ok(warnings) = lint::Pass::apply(&events);
+        warnings
+    } else {
+        Vec::new()
+    };
+    Ok((seri::generate(events, format, options)?, warnings))
+}
 
-    let mut outfile = open_output_file(args.output.clone())?;
+/// Tell where the intermediate files were kept, or remove the build directory if nothing was
+/// compiled in it
+fn report_build_directory(directory: &Path) {
+    if fs::remove_dir(directory).is_err() {
+        eprintln!("Temporary files kept in {}", directory.display());
+    }
+}
 
-    let output = match args.format {
-        Format::Tikz => generate_tikz(
-            tikz::Options {
-                template_path: template,
-            },
-            &content,
-        ),
-        Format::TikzPDF => generate_tikz_pdf(
-            &content,
-            tikz::Options {
-                template_path: template,
-            },
-            latexmk::Options {
-                input_path: None,
-                output_path: args.output,
-                save_temps: args.save_tmp,
-            },
-        ),
-        Format::AbstractLatex => generate_abstex(
-            abstex::Options {
-                template_path: template,
-            },
-            &content,
-        ),
-        Format::AbstractPDF => generate_abstract_pdf(
-            &content,
-            abstex::Options {
-                template_path: template,
-            },
-            latexmk::Options {
-                input_path: None,
-                output_path: args.output,
-                save_temps: args.save_tmp,
-            },
-        ),
-        Format::HTML => generate_html(
-            HTMLBackendOptions {
-                template_path: template,
-            },
-            &content,
+/// Run `f` in the time zone `timezone` if given, then restore the previous one, so that the time
+/// zone of a target does not leak into the next one
+fn with_timezone<T>(timezone: Option<&str>, f: impl FnOnce() -> T) -> T {
+    let Some(timezone) = timezone else {
+        return f();
+    };
+    // Read by `chrono` to find the local time zone
+    let previous = std::env::var_os("TZ");
+    std::env::set_var("TZ", timezone);
+    let result = f();
+    match previous {
+        Some(previous) => std::env::set_var("TZ", previous),
+        None => std::env::remove_var("TZ"),
+    }
+    result
+}
+
+/// Read the input, compile it and write the output, in the time zone of the arguments
+fn build(args: Args, registry: &Registry) -> Result<(), CliError> {
+    let timezone = args.timezone.clone();
+    with_timezone(timezone.as_deref(), || build_in_timezone(args, registry))
+}
+
+/// Read the input, compile it and write the output
+fn build_in_timezone(mut args: Args, registry: &Registry) -> Result<(), CliError> {
+    let pipeline = match args.pipeline_file.take() {
+        Some(path) => Some(
+            fs::read_to_string(path)
+                .map_err(CliError::CouldNotReadInput)?
+                .parse::<Pipeline>()?,
         ),
-    }?;
+        None => args.pipeline.take(),
+    };
+
+    // Paths in the document are relative to the timetable
+    let source_directory = args.file.as_deref().map(|file| {
+        Path::new(file)
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf)
+    });
+    let input_format = args.input_format.unwrap_or_else(|| {
+        args.file
+            .as_deref()
+            .map_or(InputFormat::Seri, InputFormat::from_path)
+    });
+    let content = match args.file.take() {
+        None => {
+            let mut buffer = String::new();
+            std::io::stdin().read_to_string(&mut buffer).map(|_| buffer)
+        }
+        Some(filepath) => fs::read_to_string(filepath),
+    }
+    .map_err(CliError::CouldNotReadInput)?;
+
+    let format = args.format.unwrap_or_default();
+    let lint = args.lint.unwrap_or(false);
+    let deny_warnings = args.deny_warnings.unwrap_or(false);
+    let output_path = args.output.clone();
+    let build_directory = if args.save_tmp.unwrap_or(false) {
+        Some(latexmk::create_build_directory()?)
+    } else {
+        None
+    };
+    let mut options = options(args, input_format);
+    options.latexmk.build_directory.clone_from(&build_directory);
+    options.latexmk.source_directory = source_directory;
 
-    write_output(&mut outfile, &output).map_err(CompilerError::from)
+    let result = compile(pipeline, registry, &content, format, lint, options);
+    // Shown even when the compilation failed, as that is when the files are most useful
+    if let Some(directory) = build_directory {
+        report_build_directory(&directory);
+    }
+    let (output, warnings) = result?;
+    for warning in &warnings {
+        eprintln!("Warning: {warning}");
+    }
+    if deny_warnings && !warnings.is_empty() {
+        return Err(CliError::DeniedWarnings(warnings.len()));
+    }
+
+    if let Some(parent) = output_path.as_deref().and_then(|p| Path::new(p).parent()) {
+        fs::create_dir_all(parent).map_err(CliError::CouldNotWriteOutput)?;
+    }
+    open_output_file(output_path)
+        .and_then(|mut outfile| write_output(&mut outfile, &output))
+        .map_err(CliError::CouldNotWriteOutput)
+}
+
+fn run(mut args: Args) -> Result<(), CliError> {
+    let registry = Registry::default();
+    if args.list_passes {
+        list_passes(&registry);
+        return Ok(());
+    }
+
+    let config = |args: &Args| match &args.config {
+        Some(path) => Config::read(path).map(Some),
+        None => Config::find(args.file.as_deref()),
+    };
+    match args.command.take() {
+        // The configuration is about compiling timetables, not importing them
+        Some(Command::Import { file }) => {
+            args.file = Some(file);
+            args.format = Some(Format::Seri);
+            build(args, &registry)
+        }
+        Some(Command::Build { targets }) => {
+            let config = config(&args)?.ok_or(CliError::NoConfig)?;
+            for (name, target) in config.targets(&targets)? {
+                let mut target_args = args.clone();
+                config.apply(&mut target_args, target)?;
+                eprintln!("Building {name}");
+                build(target_args, &registry)?;
+            }
+            Ok(())
+        }
+        None => {
+            if let Some(config) = config(&args)? {
+                config.apply(&mut args, None)?;
+            }
+            build(args, &registry)
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,201 @@
+//! Intermediate representation of rich text (event descriptions)
+//!
+//! Descriptions are written in `CommonMark`, with `$inline$` and `$$display$$` math. They are
+//! parsed once into a small tree of [`Block`]s and [`Inline`]s, that each backend renders in its
+//! own format.
+
+use std::iter::Peekable;
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+/// A block of rich text
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Block {
+    /// A paragraph of text
+    Paragraph(Vec<Inline>),
+    /// A heading, with its level (from 1 to 6)
+    Heading(u8, Vec<Inline>),
+    /// A list of items, numbered from the given number if ordered
+    List(Option<u64>, Vec<Vec<Self>>),
+    /// A block quote
+    Quote(Vec<Self>),
+    /// A block of code, shown verbatim
+    Code(String),
+    /// A horizontal rule
+    Rule,
+}
+
+/// A span of rich text inside a block
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Inline {
+    /// Plain text
+    Text(String),
+    /// Emphasized text
+    Emphasis(Vec<Self>),
+    /// Strongly emphasized text
+    Strong(Vec<Self>),
+    /// Inline code
+    Code(String),
+    /// A link to an URL
+    Link(String, Vec<Self>),
+    /// Inline math, in `LaTeX` notation
+    Math(String),
+    /// Display math, in `LaTeX` notation
+    DisplayMath(String),
+    /// A line break in the source, rendered as a space
+    SoftBreak,
+    /// A forced line break
+    HardBreak,
+}
+
+/// A parsed rich text document
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Markup(pub Vec<Block>);
+
+impl Markup {
+    /// Parse a `CommonMark` text
+    #[must_use]
+    pub fn parse(text: &str) -> Self {
+        let mut events = Parser::new_ext(text, Options::ENABLE_MATH).peekable();
+        Self(blocks(&mut events))
+    }
+}
+
+const fn is_block_start(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Start(
+            Tag::Paragraph
+                | Tag::Heading { .. }
+                | Tag::BlockQuote(_)
+                | Tag::CodeBlock(_)
+                | Tag::HtmlBlock
+                | Tag::List(_)
+                | Tag::Item
+                | Tag::FootnoteDefinition(_)
+        ) | Event::Rule
+    )
+}
+
+/// Collect the text of all events until the end of the current tag, which is consumed
+fn text<'a>(events: &mut impl Iterator<Item = Event<'a>>) -> String {
+    let mut res = String::new();
+    for event in events.by_ref() {
+        match event {
+            Event::End(_) => break,
+            Event::Text(t) | Event::Code(t) | Event::Html(t) | Event::InlineHtml(t) => {
+                res += &t;
+            }
+            _ => (),
+        }
+    }
+    res
+}
+
+/// Parse blocks until the end of the current tag, which is consumed
+fn blocks<'a>(events: &mut Peekable<impl Iterator<Item = Event<'a>>>) -> Vec<Block> {
+    let mut res = Vec::new();
+    while let Some(event) = events.peek() {
+        if !is_block_start(event) {
+            if matches!(event, Event::End(_)) {
+                events.next();
+                break;
+            }
+            // Tight list items contain text directly, without a paragraph
+            res.push(Block::Paragraph(inlines(events)));
+            continue;
+        }
+        match events.next() {
+            Some(Event::Start(Tag::Paragraph)) => {
+                res.push(Block::Paragraph(inlines(events)));
+                events.next();
+            }
+            Some(Event::Start(Tag::Heading { level, .. })) => {
+                #[allow(clippy::cast_possible_truncation)]
+                res.push(Block::Heading(level as u8, inlines(events)));
+                events.next();
+            }
+            Some(Event::Start(Tag::BlockQuote(_))) => res.push(Block::Quote(blocks(events))),
+            Some(Event::Start(Tag::CodeBlock(_) | Tag::HtmlBlock)) => {
+                res.push(Block::Code(text(events)));
+            }
+            Some(Event::Start(Tag::List(start))) => {
+                let mut items = Vec::new();
+                while matches!(events.peek(), Some(Event::Start(Tag::Item))) {
+                    events.next();
+                    items.push(blocks(events));
+                }
+                events.next();
+                res.push(Block::List(start, items));
+            }
+            Some(Event::Rule) => res.push(Block::Rule),
+            // Other containers (footnotes) are flattened
+            _ => res.extend(blocks(events)),
+        }
+    }
+    res
+}
+
+/// Parse inlines until the end of the current tag or the next block, which are not consumed
+fn inlines<'a>(events: &mut Peekable<impl Iterator<Item = Event<'a>>>) -> Vec<Inline> {
+    let mut res = Vec::new();
+    while let Some(event) = events.peek() {
+        if is_block_start(event) || matches!(event, Event::End(_)) {
+            break;
+        }
+        let inline = match events.next() {
+            Some(Event::Text(t) | Event::Html(t) | Event::InlineHtml(t)) => {
+                Inline::Text(t.into_string())
+            }
+            Some(Event::Code(c)) => Inline::Code(c.into_string()),
+            Some(Event::InlineMath(m)) => Inline::Math(m.into_string()),
+            Some(Event::DisplayMath(m)) => Inline::DisplayMath(m.into_string()),
+            Some(Event::SoftBreak) => Inline::SoftBreak,
+            Some(Event::HardBreak) => Inline::HardBreak,
+            Some(Event::Start(tag)) => {
+                let children = inlines(events);
+                events.next();
+                match tag {
+                    Tag::Emphasis => Inline::Emphasis(children),
+                    Tag::Strong => Inline::Strong(children),
+                    Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => {
+                        Inline::Link(dest_url.into_string(), children)
+                    }
+                    _ => {
+                        res.extend(children);
+                        continue;
+                    }
+                }
+            }
+            _ => continue,
+        };
+        res.push(inline);
+    }
+    res
+}
+
+#[test]
+fn test_markup_parse() {
+    let markup = Markup::parse("Some *emphasis* and $x^2$\n\n- one\n- [two](https://example.org)");
+    assert_eq!(
+        markup,
+        Markup(vec![
+            Block::Paragraph(vec![
+                Inline::Text("Some ".to_owned()),
+                Inline::Emphasis(vec![Inline::Text("emphasis".to_owned())]),
+                Inline::Text(" and ".to_owned()),
+                Inline::Math("x^2".to_owned()),
+            ]),
+            Block::List(
+                None,
+                vec![
+                    vec![Block::Paragraph(vec![Inline::Text("one".to_owned())])],
+                    vec![Block::Paragraph(vec![Inline::Link(
+                        "https://example.org".to_owned(),
+                        vec![Inline::Text("two".to_owned())]
+                    )])],
+                ]
+            ),
+        ])
+    );
+}
@@ -1,10 +1,25 @@
 //! Compilation passes
 
+use crate::event::Event;
+
 pub mod abstex;
+pub mod agenda;
+pub mod cache;
+pub mod csv;
+pub mod filter;
 pub mod html;
+pub mod ics;
 pub mod latexmk;
+pub mod lint;
 pub mod parser;
+pub mod pentabarf;
+pub mod plugin;
+pub mod recurrence;
+pub mod seri;
+pub mod svg;
 pub mod tikz;
+pub mod translate;
+pub mod typst;
 
 /// A trait defining compilation passes
 /// Compilation passes should be chainable
@@ -17,7 +32,7 @@ pub mod tikz;
 /// ```rust
 /// // Example of a pass that truncates a string to a given length
 ///
-/// use crate::passes::CompilingPass;
+/// use seri::passes::{CompilingPass, PassInput};
 ///
 /// struct TruncatePass;
 ///
@@ -89,3 +104,6 @@ pub trait PassInput: Sized {
         P::apply_with(self, ctx)
     }
 }
+
+impl PassInput for &str {}
+impl PassInput for Vec<Event> {}
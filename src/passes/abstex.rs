@@ -1,12 +1,15 @@
 //! `LaTeX` abstracts backend
 
-use std::str::FromStr;
+use std::{collections::BTreeSet, fmt::Write, str::FromStr};
 
 use chrono::{DateTime, Days, Local};
 
 use crate::{
     event::{find_bounding_box, Event, InvalidDatetime, Type},
-    passes::CompilingPass,
+    language::{self, Flags},
+    locale::Locale,
+    markup::{Block, Inline, Markup},
+    passes::{latexmk::event_marker, CompilingPass},
     templating,
 };
 
@@ -14,9 +17,15 @@ use crate::{
 pub struct Pass {}
 
 /// Options for the `LaTeX` abstracts backend
+#[derive(Default, Clone)]
 pub struct Options {
     /// Path to the template file. If not set, the default template (`data/template_abstex.tex`) will be used.
     pub template_path: Option<String>,
+    /// Language of the dates and of the titles of events without one, and main language of the
+    /// document
+    pub locale: Locale,
+    /// Flags shown instead of the codes of the languages of the talks
+    pub flags: Flags,
 }
 
 /// Error occuring when compiling an event list to `LaTeX` abstracts.
@@ -52,40 +61,180 @@ impl CompilingPass<Vec<Event>> for Pass {
     type Error = Error;
 
     fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
-        Self::apply_with(
-            events,
-            Options {
-                template_path: None,
-            },
-        )
+        Self::apply_with(events, Options::default())
     }
 }
 
-fn day_delimiter(day: &DateTime<Local>) -> String {
-    format!(r"\section{{{}}}", day.format("%A, %B %e"))
+fn day_delimiter(day: &DateTime<Local>, locale: Locale) -> String {
+    format!(r"\section{{{}}}", locale.day(day))
 }
 
-fn talk_title(e: &Event) -> String {
+fn talk_title(e: &Event, locale: Locale, flags: &Flags) -> String {
     let mut r = r"\subsection{".to_owned();
-    r += &talk_language(e);
-    r += &e.title;
+    r += &talk_languages(e, flags);
+    r += e.display_title(locale);
     r += "}\n";
     r
 }
 
 fn talk_subtitle(e: &Event) -> String {
     let mut r = r"\paragraph{} \textit{".to_owned();
-    r += &format!("{}", e.start_date.time().format("%H:%M"));
+    let _ = write!(r, "{}", e.start_date.time().format("%H:%M"));
     if !e.speakers.is_empty() {
-        r += &format!(" - {}", e.speakers_string());
+        let _ = write!(r, " - {}", e.speakers_string());
     }
     r += "}\n";
     r
 }
 
-fn talk_language(e: &Event) -> String {
-    e.language
-        .map_or_else(String::new, |l| "\\".to_owned() + l.to_639_3() + " ")
+/// Escape the characters that have a special meaning in `LaTeX`
+#[must_use]
+pub fn escape(text: &str) -> String {
+    let mut r = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => r += r"\textbackslash{}",
+            '~' => r += r"\textasciitilde{}",
+            '^' => r += r"\textasciicircum{}",
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                r.push('\\');
+                r.push(c);
+            }
+            _ => r.push(c),
+        }
+    }
+    r
+}
+
+/// Escape the characters of a URL that have a special meaning in the argument of `\href`, and
+/// percent-encode those that cannot be escaped there
+fn escape_url(url: &str) -> String {
+    let mut r = String::with_capacity(url.len());
+    for c in url.chars() {
+        match c {
+            '\\' => r += r"\%5C",
+            '{' => r += r"\%7B",
+            '}' => r += r"\%7D",
+            '%' | '#' => {
+                r.push('\\');
+                r.push(c);
+            }
+            _ => r.push(c),
+        }
+    }
+    r
+}
+
+/// Characters that can delimit the argument of `\verb`
+const VERB_DELIMITERS: &str = "|!+=@/:;\"'.,-?<>()[]0123456789";
+
+/// Typeset a line of code verbatim, with `\verb` and a delimiter that is not in the line, or with
+/// escaped text if the line contains every delimiter
+fn verb(line: &str) -> String {
+    VERB_DELIMITERS
+        .chars()
+        .find(|&d| !line.contains(d))
+        .map_or_else(
+            || format!(r"\texttt{{{}}}", escape(line).replace(' ', "~")),
+            |d| format!(r"\verb{d}{line}{d}"),
+        )
+}
+
+fn inlines_to_latex(inlines: &[Inline]) -> String {
+    inlines.iter().map(inline_to_latex).collect()
+}
+
+fn inline_to_latex(inline: &Inline) -> String {
+    match inline {
+        Inline::Text(text) => escape(text),
+        Inline::Emphasis(content) => format!(r"\emph{{{}}}", inlines_to_latex(content)),
+        Inline::Strong(content) => format!(r"\textbf{{{}}}", inlines_to_latex(content)),
+        Inline::Code(code) => format!(r"\texttt{{{}}}", escape(code)),
+        Inline::Link(url, content) => format!(
+            r"\href{{{}}}{{{}}}",
+            escape_url(url),
+            inlines_to_latex(content)
+        ),
+        Inline::Math(math) => format!("${math}$"),
+        Inline::DisplayMath(math) => format!(r"\[{math}\]"),
+        Inline::SoftBreak => "\n".to_owned(),
+        Inline::HardBreak => "\\\\\n".to_owned(),
+    }
+}
+
+fn blocks_to_latex(blocks: &[Block]) -> String {
+    blocks
+        .iter()
+        .map(block_to_latex)
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn block_to_latex(block: &Block) -> String {
+    match block {
+        Block::Paragraph(content) => inlines_to_latex(content),
+        Block::Heading(_, content) => format!(r"\subsubsection*{{{}}}", inlines_to_latex(content)),
+        Block::List(start, items) => {
+            let env = if start.is_some() {
+                "enumerate"
+            } else {
+                "itemize"
+            };
+            let mut r = format!(r"\begin{{{env}}}");
+            if let Some(start) = start {
+                let _ = write!(r, r"\setcounter{{enumi}}{{{}}}", start.saturating_sub(1));
+            }
+            for item in items {
+                let _ = write!(r, "\n\\item {}", blocks_to_latex(item));
+            }
+            let _ = write!(r, "\n\\end{{{env}}}");
+            r
+        }
+        Block::Quote(content) => format!(
+            "\\begin{{quote}}\n{}\n\\end{{quote}}",
+            blocks_to_latex(content)
+        ),
+        // Not a verbatim environment, which would end at any `\end{verbatim}` in the code
+        Block::Code(code) => format!(
+            "\\par\\noindent{{\\ttfamily\n{}}}\\par",
+            code.lines().map(verb).collect::<Vec<_>>().join("\\\\\n")
+        ),
+        Block::Rule => r"\noindent\rule{\linewidth}{0.4pt}".to_owned(),
+    }
+}
+
+fn talk_languages(e: &Event, flags: &Flags) -> String {
+    let mut r = String::new();
+    for &l in &e.languages {
+        let code = language::code(l).to_uppercase();
+        match flags.get(l) {
+            Some(flag) => {
+                let _ = write!(r, r"\seriflag{{{code}}}{{{}}}", escape(flag));
+            }
+            None => {
+                let _ = write!(r, r"\serilang{{{code}}}");
+            }
+        }
+    }
+    r
+}
+
+/// Load `babel` with `pdfLaTeX`, or `polyglossia` with `XeLaTeX` and `LuaLaTeX`, for the main
+/// language and the other languages of the talks
+fn language_setup(main: &str, languages: &BTreeSet<&str>) -> String {
+    let others: Vec<&str> = languages.iter().copied().filter(|l| *l != main).collect();
+    let mut babel = others.clone();
+    let main_option = format!("main={main}");
+    babel.push(&main_option);
+    let mut r = format!(
+        "\\ifpdftex\n\\usepackage[{}]{{babel}}\n\\else\n\\usepackage{{polyglossia}}\n\\setdefaultlanguage{{{main}}}\n",
+        babel.join(",")
+    );
+    if !others.is_empty() {
+        let _ = writeln!(r, r"\setotherlanguages{{{}}}", others.join(","));
+    }
+    r += r"\fi";
+    r
 }
 
 impl CompilingPass<Vec<Event>, Options> for Pass {
@@ -93,12 +242,7 @@ impl CompilingPass<Vec<Event>, Options> for Pass {
     type Error = Error;
 
     fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
-        Self::apply_with(
-            events,
-            Options {
-                template_path: None,
-            },
-        )
+        Self::apply_with(events, Options::default())
     }
 
     #[allow(clippy::cast_possible_truncation)]
@@ -115,36 +259,71 @@ impl CompilingPass<Vec<Event>, Options> for Pass {
 
         let mut day = bb.first_day()? - Days::new(1);
 
+        // Languages known to LaTeX, to set up hyphenation
+        let main = language::latex_name(options.locale.language()).unwrap_or("english");
+        let mut languages = BTreeSet::from([main]);
+
         // Display all our event
         for e in events {
             if e.start_date.date_naive() > day.date_naive() {
                 day = e.start_date;
-                r += &day_delimiter(&day);
+                r += &day_delimiter(&day, options.locale);
             }
             match &e.event_type {
                 Type::Talk | Type::Fun => {
-                    r += &talk_title(&e);
+                    r += "\n";
+                    r += &event_marker(&e);
+                    r += "\n";
+                    // Hyphenate the talk in its main language
+                    let talk_language = e.languages.first().and_then(|l| language::latex_name(*l));
+                    if let Some(name) = talk_language {
+                        languages.insert(name);
+                        let _ = writeln!(r, r"\begin{{otherlanguage}}{{{name}}}");
+                    }
+                    r += &talk_title(&e, options.locale, &options.flags);
                     r += &talk_subtitle(&e);
                     r += &e
                         .description
-                        .map(|d| r"\paragraph{} ".to_owned() + &d)
+                        .map(|d| {
+                            r"\paragraph{} ".to_owned() + &blocks_to_latex(&Markup::parse(&d).0)
+                        })
                         .unwrap_or_default();
+                    if talk_language.is_some() {
+                        r += "\n\\end{otherlanguage}";
+                    }
                     r += "\n\n";
                 }
                 _ => (),
             }
         }
 
+        let (first_day, last_day) = (bb.first_day()?, bb.last_day()?);
+        let locale = options.locale;
         let t = templating::replace(
             &template,
-            "BEGIN_DATE",
-            &format!("{}", bb.first_day()?.format("%A, %B %e")),
-        )?;
-        let t = templating::replace(
-            &t,
-            "END_DATE",
-            &format!("{}", bb.last_day()?.format("%A, %B %e")),
+            "DATES",
+            &locale.date_range(&first_day, &last_day),
         )?;
+        let t = templating::replace(&t, "BEGIN_DATE", &locale.day(&first_day))?;
+        let t = templating::replace(&t, "END_DATE", &locale.day(&last_day))?;
+        let t = templating::replace(&t, "LANGUAGES", &language_setup(main, &languages))?;
         Ok(templating::replace(&t, "ABSTRACTS", &r)?)
     }
 }
+
+#[test]
+fn test_block_to_latex() {
+    let link = Inline::Link(
+        r"https://example.org/{a}\b%20#c".to_owned(),
+        vec![Inline::Text("link".to_owned())],
+    );
+    assert_eq!(
+        inline_to_latex(&link),
+        r"\href{https://example.org/\%7Ba\%7D\%5Cb\%20\#c}{link}"
+    );
+    let code = Block::Code("\\end{verbatim}\n  a|b\n".to_owned());
+    assert_eq!(
+        block_to_latex(&code),
+        "\\par\\noindent{\\ttfamily\n\\verb|\\end{verbatim}|\\\\\n\\verb!  a|b!}\\par"
+    );
+}
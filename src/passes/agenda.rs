@@ -0,0 +1,272 @@
+//! Markdown and plain-text agenda backends, to paste the programme in announcements
+
+use std::fmt::Write;
+
+use chrono::{Duration, NaiveDate};
+use isolang::Language;
+
+use crate::{
+    event::Event,
+    locale::Locale,
+    markup::{Block, Inline, Markup},
+    passes::CompilingPass,
+};
+
+/// Backend outputing events to a day-by-day agenda in Markdown
+pub struct Markdown {}
+
+/// Backend outputing events to a day-by-day agenda in plain text
+pub struct Text {}
+
+/// Options for the agenda backends
+#[derive(Default, Clone)]
+pub struct Options {
+    /// Width at which the plain text is wrapped. If not set, 72 columns will be used.
+    pub width: Option<usize>,
+    /// Whether the abstracts are shown under each event
+    pub abstracts: bool,
+    /// Language of the day headings
+    pub locale: Locale,
+}
+
+/// Format the time range of an event, such as `10:10-10:40`
+fn time_range(e: &Event, separator: &str) -> String {
+    let start = e.start_date.format("%H:%M");
+    if e.duration == 0 {
+        start.to_string()
+    } else {
+        let end = e.start_date + Duration::minutes(i64::from(e.duration));
+        format!("{start}{separator}{}", end.format("%H:%M"))
+    }
+}
+
+/// Format the speakers and languages of an event, such as `alice, bob (English, French)`
+fn byline(e: &Event) -> String {
+    let mut r = e.speakers_string();
+    if !e.languages.is_empty() {
+        if !r.is_empty() {
+            r.push(' ');
+        }
+        let names: Vec<&str> = e.languages.iter().map(Language::to_name).collect();
+        let _ = write!(r, "({})", names.join(", "));
+    }
+    r
+}
+
+/// Group events by day, in chronological order
+fn days(mut events: Vec<Event>) -> Vec<(NaiveDate, Vec<Event>)> {
+    events.sort_by_key(|e| e.start_date);
+    let mut days: Vec<(NaiveDate, Vec<Event>)> = Vec::new();
+    for e in events {
+        let day = e.start_date.date_naive();
+        match days.last_mut() {
+            Some((last, day_events)) if *last == day => day_events.push(e),
+            _ => days.push((day, vec![e])),
+        }
+    }
+    days
+}
+
+/// Escape the characters that could be read as Markdown formatting
+fn escape_markdown(text: &str) -> String {
+    let mut r = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>' | '#') {
+            r.push('\\');
+        }
+        r.push(c);
+    }
+    r
+}
+
+fn inlines_to_text(inlines: &[Inline]) -> String {
+    inlines.iter().map(inline_to_text).collect()
+}
+
+fn inline_to_text(inline: &Inline) -> String {
+    match inline {
+        Inline::Text(text)
+        | Inline::Code(text)
+        | Inline::Math(text)
+        | Inline::DisplayMath(text) => text.clone(),
+        Inline::Emphasis(content) | Inline::Strong(content) => inlines_to_text(content),
+        Inline::Link(url, content) => {
+            let content = inlines_to_text(content);
+            if content == *url {
+                content
+            } else {
+                format!("{content} ({url})")
+            }
+        }
+        Inline::SoftBreak => " ".to_owned(),
+        Inline::HardBreak => "\n".to_owned(),
+    }
+}
+
+/// Render rich text as plain text, wrapped to `width` columns and indented by `indent`
+fn blocks_to_text(blocks: &[Block], width: usize, indent: &str) -> String {
+    blocks
+        .iter()
+        .map(|block| block_to_text(block, width, indent))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn block_to_text(block: &Block, width: usize, indent: &str) -> String {
+    match block {
+        Block::Paragraph(content) | Block::Heading(_, content) => textwrap::fill(
+            &inlines_to_text(content),
+            textwrap::Options::new(width)
+                .initial_indent(indent)
+                .subsequent_indent(indent),
+        ),
+        Block::List(start, items) => {
+            let mut number = start.unwrap_or_default();
+            let mut r = Vec::new();
+            for item in items {
+                let marker = if start.is_some() {
+                    number += 1;
+                    format!("{}. ", number - 1)
+                } else {
+                    "- ".to_owned()
+                };
+                // The item is indented under its text, and the marker put before its first line
+                let inner = format!("{indent}{}", " ".repeat(marker.len()));
+                let text = blocks_to_text(item, width, &inner);
+                let text = text.strip_prefix(&inner).unwrap_or(&text);
+                r.push(format!("{indent}{marker}{text}"));
+            }
+            r.join("\n")
+        }
+        Block::Quote(content) => blocks_to_text(content, width, &format!("{indent}> ")),
+        Block::Code(code) => code
+            .lines()
+            .map(|line| format!("{indent}{line}"))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Block::Rule => indent.to_owned() + &"-".repeat(width.saturating_sub(indent.len())),
+    }
+}
+
+impl CompilingPass<Vec<Event>, Options> for Markdown {
+    type Residual = String;
+    type Error = std::convert::Infallible;
+
+    fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
+        Self::apply_with(events, Options::default())
+    }
+
+    fn apply_with(events: Vec<Event>, options: Options) -> Result<Self::Residual, Self::Error> {
+        let mut r = String::new();
+        for (day, day_events) in days(events) {
+            let _ = writeln!(r, "## {}\n", options.locale.day(&day));
+            for e in &day_events {
+                let _ = write!(
+                    r,
+                    "- **{}** {}",
+                    time_range(e, "–"),
+                    escape_markdown(e.display_title(options.locale))
+                );
+                let byline = byline(e);
+                if !byline.is_empty() {
+                    let _ = write!(r, " — *{}*", escape_markdown(&byline));
+                }
+                r.push('\n');
+                if let (true, Some(description)) = (options.abstracts, &e.description) {
+                    // The abstract is already Markdown, it is indented to stay in the list item
+                    r.push('\n');
+                    for line in description.lines() {
+                        if line.trim().is_empty() {
+                            r.push('\n');
+                        } else {
+                            let _ = writeln!(r, "  {line}");
+                        }
+                    }
+                    r.push('\n');
+                }
+            }
+            r.push('\n');
+        }
+        Ok(r.trim_end().to_owned() + "\n")
+    }
+}
+
+impl CompilingPass<Vec<Event>, Options> for Text {
+    type Residual = String;
+    type Error = std::convert::Infallible;
+
+    fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
+        Self::apply_with(events, Options::default())
+    }
+
+    fn apply_with(events: Vec<Event>, options: Options) -> Result<Self::Residual, Self::Error> {
+        let width = options.width.unwrap_or(72);
+        let mut r = String::new();
+        for (day, day_events) in days(events) {
+            let heading = options.locale.day(&day);
+            let _ = writeln!(r, "{heading}\n{}\n", "=".repeat(heading.chars().count()));
+
+            // Titles and details are aligned after the longest time range of the day
+            let ranges: Vec<String> = day_events.iter().map(|e| time_range(e, "-")).collect();
+            let column = ranges.iter().map(String::len).max().unwrap_or(0) + 2;
+            let indent = " ".repeat(column);
+
+            for (e, range) in day_events.iter().zip(&ranges) {
+                let first = format!("{range:column$}");
+                let wrap = |text: &str, first: &str| {
+                    textwrap::fill(
+                        text,
+                        textwrap::Options::new(width)
+                            .initial_indent(first)
+                            .subsequent_indent(&indent),
+                    )
+                };
+                let _ = writeln!(r, "{}", wrap(e.display_title(options.locale), &first));
+                let byline = byline(e);
+                if !byline.is_empty() {
+                    let _ = writeln!(r, "{}", wrap(&byline, &indent));
+                }
+                if let (true, Some(description)) = (options.abstracts, &e.description) {
+                    let markup = Markup::parse(description);
+                    let _ = writeln!(r, "\n{}", blocks_to_text(&markup.0, width, &indent));
+                }
+                r.push('\n');
+            }
+        }
+        Ok(r.trim_end().to_owned() + "\n")
+    }
+}
+
+#[test]
+fn test_text_agenda() {
+    use std::str::FromStr;
+
+    let event = Event::from_str(
+        "title: Cryptography 101\ndate: 2023-04-27 10:10\nduration: 30\nspeakers: alice, bob\nlang: en\n\nLorem ipsum dolor sit amet",
+    )
+    .unwrap();
+    let text = Text::apply_with(
+        vec![event],
+        Options {
+            width: Some(25),
+            abstracts: true,
+            ..Options::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        text,
+        "Thursday, April 27\n==================\n\n10:10-10:40  Cryptography\n             101\n             alice, bob\n             (English)\n\n             Lorem ipsum\n             dolor sit\n             amet\n"
+    );
+}
+
+#[test]
+fn test_markup_to_text() {
+    let markup = Markup::parse(
+        "Some *emphasis* and a [link](https://a.b).\n\n1. one\n2. two more words\n\n> quoted",
+    );
+    assert_eq!(
+        blocks_to_text(&markup.0, 18, "  "),
+        "  Some emphasis\n  and a link\n  (https://a.b).\n\n  1. one\n  2. two more\n     words\n\n  > quoted"
+    );
+}
@@ -0,0 +1,358 @@
+//! Cache of the results of expensive passes, keyed by a hash of their input
+//!
+//! Any [`CompilingPass`] can be wrapped in [`Cached`], as long as its input and options can be
+//! hashed and its residual stored as bytes. Passes that only know the files they read once they
+//! are applied, such as `LaTeX` documents including images, are wrapped in [`CachedRecorded`]
+//! instead. Entries that have not been used for [`Options::max_age`] are removed whenever a new
+//! one is stored.
+
+use std::{
+    fmt::Write,
+    fs,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::passes::CompilingPass;
+
+/// Applies the pass `P`, or reads its result from the cache if it was already applied to the
+/// same input, with the same options and dependencies
+pub struct Cached<P> {
+    pass: PhantomData<P>,
+}
+
+/// Options for the cache pass
+#[derive(Clone)]
+pub struct Options<C> {
+    /// Directory where the results are stored
+    pub directory: PathBuf,
+    /// Files whose contents are part of the key, such as templates
+    pub dependencies: Vec<PathBuf>,
+    /// Entries unused for longer than this are evicted
+    pub max_age: Duration,
+    /// Options of the cached pass
+    pub inner: C,
+}
+
+/// Default for [`Options::max_age`]: 30 days
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_hours(30 * 24);
+
+impl<C> Options<C> {
+    /// The same cache, for a pass with options `inner`
+    #[must_use]
+    pub fn with_inner<D>(self, inner: D) -> Options<D> {
+        Options {
+            directory: self.directory,
+            dependencies: self.dependencies,
+            max_age: self.max_age,
+            inner,
+        }
+    }
+}
+
+/// A pass residual that can be stored in the cache
+pub trait Cacheable: Sized {
+    /// Serialize the residual
+    fn to_bytes(&self) -> Vec<u8>;
+    /// Read back a serialized residual, if it is valid
+    fn from_bytes(bytes: Vec<u8>) -> Option<Self>;
+}
+
+impl Cacheable for Vec<u8> {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Option<Self> {
+        Some(bytes)
+    }
+}
+
+impl Cacheable for String {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> Option<Self> {
+        Self::from_utf8(bytes).ok()
+    }
+}
+
+/// A pass residual that lists the files read to produce it
+pub trait Recorded {
+    /// The part of the residual stored in the cache
+    type Stored: Cacheable;
+    /// Split the residual into its stored part and the files read, if they are known
+    fn into_parts(self) -> (Self::Stored, Option<Vec<PathBuf>>);
+}
+
+/// Applies the pass `P` like [`Cached`], with the files it read last time as dependencies
+///
+/// The entry of the input lists the files recorded by the pass, and the result is stored under a
+/// key covering their contents too, so that changing any of them misses the cache. Results whose
+/// files are not known are not stored.
+pub struct CachedRecorded<P> {
+    pass: PhantomData<P>,
+}
+
+/// Feeds the data given to [`Hash`] to SHA-256, which is stable across runs and versions
+struct ContentHasher(Sha256);
+
+impl Hasher for ContentHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        let digest = self.0.clone().finalize();
+        digest
+            .iter()
+            .take(8)
+            .fold(0, |acc, byte| (acc << 8) | u64::from(*byte))
+    }
+}
+
+/// Compute the name of the cache entry of a pass application
+fn key<P, T: Hash, C: Hash>(data: &T, ctx: &C, dependencies: &[PathBuf]) -> String {
+    let mut hasher = ContentHasher(Sha256::new());
+    std::any::type_name::<P>().hash(&mut hasher);
+    data.hash(&mut hasher);
+    ctx.hash(&mut hasher);
+    for dependency in dependencies {
+        fs::read(dependency).ok().hash(&mut hasher);
+    }
+    hasher
+        .0
+        .finalize()
+        .iter()
+        .fold(String::new(), |mut r, byte| {
+            let _ = write!(r, "{byte:02x}");
+            r
+        })
+}
+
+/// Write a cache entry, through a temporary file so that concurrent builds never read half of it
+fn store(path: &Path, bytes: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    let partial = path.with_extension(format!("{}.partial", std::process::id()));
+    fs::write(&partial, bytes)?;
+    fs::rename(partial, path)
+}
+
+/// Mark a cache entry as used now, so that it is not evicted
+fn touch(path: &Path) -> Result<(), std::io::Error> {
+    fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
+/// Remove the entries of `directory` that have not been used for longer than `max_age`
+fn evict(directory: &Path, max_age: Duration) -> Result<(), std::io::Error> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let unused = entry.metadata()?.modified()?.elapsed().unwrap_or_default();
+        if unused > max_age {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+impl<T, C, P> CompilingPass<T, Options<C>> for Cached<P>
+where
+    T: Hash,
+    C: Hash,
+    P: CompilingPass<T, C>,
+    P::Residual: Cacheable,
+    P::Error: From<std::io::Error>,
+{
+    type Residual = P::Residual;
+    type Error = P::Error;
+
+    fn apply(data: T) -> Result<Self::Residual, Self::Error> {
+        P::apply(data)
+    }
+
+    fn apply_with(data: T, options: Options<C>) -> Result<Self::Residual, Self::Error> {
+        let key = key::<P, T, C>(&data, &options.inner, &options.dependencies);
+        let path = options.directory.join(key);
+        if let Some(residual) = fs::read(&path).ok().and_then(P::Residual::from_bytes) {
+            let _ = touch(&path);
+            return Ok(residual);
+        }
+
+        let residual = P::apply_with(data, options.inner)?;
+        // The cache is only an optimisation: failing to write it must not fail the pass
+        let _ = store(&path, &residual.to_bytes());
+        let _ = evict(&options.directory, options.max_age);
+        Ok(residual)
+    }
+}
+
+/// Path of the result of a [`CachedRecorded`] pass whose input entry is `inputs`
+fn recorded_path<P>(inputs: &Path, recorded: &[PathBuf]) -> PathBuf {
+    inputs.with_file_name(key::<P, _, _>(&inputs.file_name(), &(), recorded))
+}
+
+impl<T, C, P> CompilingPass<T, Options<C>> for CachedRecorded<P>
+where
+    T: Hash,
+    C: Hash,
+    P: CompilingPass<T, C>,
+    P::Residual: Recorded,
+    P::Error: From<std::io::Error>,
+{
+    type Residual = <P::Residual as Recorded>::Stored;
+    type Error = P::Error;
+
+    fn apply(data: T) -> Result<Self::Residual, Self::Error> {
+        P::apply(data).map(|residual| residual.into_parts().0)
+    }
+
+    fn apply_with(data: T, options: Options<C>) -> Result<Self::Residual, Self::Error> {
+        let key = key::<P, T, C>(&data, &options.inner, &options.dependencies);
+        let inputs = options.directory.join(key + ".inputs");
+        if let Ok(list) = fs::read_to_string(&inputs) {
+            let recorded: Vec<PathBuf> = list.lines().map(PathBuf::from).collect();
+            let path = recorded_path::<P>(&inputs, &recorded);
+            if let Some(residual) = fs::read(&path).ok().and_then(Cacheable::from_bytes) {
+                let _ = touch(&inputs);
+                let _ = touch(&path);
+                return Ok(residual);
+            }
+        }
+
+        let (residual, recorded) = P::apply_with(data, options.inner)?.into_parts();
+        if let Some(recorded) = recorded {
+            let list: Vec<_> = recorded.iter().map(|p| p.to_string_lossy()).collect();
+            let _ = store(&inputs, list.join("\n").as_bytes());
+            let _ = store(
+                &recorded_path::<P>(&inputs, &recorded),
+                &residual.to_bytes(),
+            );
+            let _ = evict(&options.directory, options.max_age);
+        }
+        Ok(residual)
+    }
+}
+
+#[test]
+fn test_cache() {
+    struct Count {}
+    static APPLIED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    impl CompilingPass<&str> for Count {
+        type Residual = String;
+        type Error = std::io::Error;
+        fn apply(data: &str) -> Result<Self::Residual, Self::Error> {
+            APPLIED.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(data.to_uppercase())
+        }
+    }
+
+    let directory = std::env::temp_dir().join(format!("seri-cache-test-{}", std::process::id()));
+    let options = |max_age| Options {
+        directory: directory.clone(),
+        dependencies: Vec::new(),
+        max_age,
+        inner: (),
+    };
+    assert_eq!(
+        Cached::<Count>::apply_with("a", options(DEFAULT_MAX_AGE)).unwrap(),
+        "A"
+    );
+    assert_eq!(
+        Cached::<Count>::apply_with("a", options(DEFAULT_MAX_AGE)).unwrap(),
+        "A"
+    );
+    assert_eq!(
+        Cached::<Count>::apply_with("b", options(DEFAULT_MAX_AGE)).unwrap(),
+        "B"
+    );
+    assert_eq!(APPLIED.load(std::sync::atomic::Ordering::SeqCst), 2);
+    assert_eq!(fs::read_dir(&directory).unwrap().count(), 2);
+
+    // Storing an entry evicts the old ones
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(
+        Cached::<Count>::apply_with("c", options(Duration::from_millis(10))).unwrap(),
+        "C"
+    );
+    assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+    assert_eq!(
+        Cached::<Count>::apply_with("a", options(DEFAULT_MAX_AGE)).unwrap(),
+        "A"
+    );
+    assert_eq!(APPLIED.load(std::sync::atomic::Ordering::SeqCst), 4);
+
+    // A write failure does not fail the pass
+    fs::remove_dir_all(&directory).unwrap();
+    fs::write(&directory, "").unwrap();
+    assert_eq!(
+        Cached::<Count>::apply_with("d", options(DEFAULT_MAX_AGE)).unwrap(),
+        "D"
+    );
+    fs::remove_file(directory).unwrap();
+}
+
+#[test]
+fn test_cache_recorded() {
+    struct Include {}
+    static APPLIED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    struct Document(String, Option<Vec<PathBuf>>);
+    impl Recorded for Document {
+        type Stored = String;
+        fn into_parts(self) -> (Self::Stored, Option<Vec<PathBuf>>) {
+            (self.0, self.1)
+        }
+    }
+    // Reads the file named by the input, like a document including it
+    impl CompilingPass<&Path> for Include {
+        type Residual = Document;
+        type Error = std::io::Error;
+        fn apply(path: &Path) -> Result<Self::Residual, Self::Error> {
+            APPLIED.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(Document(
+                fs::read_to_string(path)?,
+                path.exists().then(|| vec![path.to_owned()]),
+            ))
+        }
+    }
+
+    let root = std::env::temp_dir().join(format!("seri-recorded-test-{}", std::process::id()));
+    let included = root.join("included.tex");
+    fs::create_dir_all(&root).unwrap();
+    fs::write(&included, "first").unwrap();
+    let options = || Options {
+        directory: root.join("cache"),
+        dependencies: Vec::new(),
+        max_age: DEFAULT_MAX_AGE,
+        inner: (),
+    };
+    let applied = || APPLIED.load(std::sync::atomic::Ordering::SeqCst);
+
+    assert_eq!(
+        CachedRecorded::<Include>::apply_with(included.as_path(), options()).unwrap(),
+        "first"
+    );
+    assert_eq!(
+        CachedRecorded::<Include>::apply_with(included.as_path(), options()).unwrap(),
+        "first"
+    );
+    assert_eq!(applied(), 1);
+
+    // Changing the included file misses the cache
+    fs::write(&included, "second").unwrap();
+    assert_eq!(
+        CachedRecorded::<Include>::apply_with(included.as_path(), options()).unwrap(),
+        "second"
+    );
+    assert_eq!(applied(), 2);
+    fs::remove_dir_all(root).unwrap();
+}
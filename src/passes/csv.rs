@@ -0,0 +1,249 @@
+//! CSV (spreadsheet) input and output passes
+
+use std::{collections::BTreeSet, str::FromStr};
+
+use crate::{
+    event::{Event, ParsingError},
+    language,
+    passes::CompilingPass,
+    syntax::{RawEvent, Setting},
+};
+
+/// Reads events from a CSV document with a header row, one event per row
+pub struct Import {}
+
+/// Writes events to a CSV document with a header row, one event per row
+pub struct Export {}
+
+/// Name of the column holding a given setting
+///
+/// Written as `KEY=COLUMN`, for instance `title=Talk title`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ColumnMapping {
+    /// The setting key, such as `title` or `speakers`
+    pub key: String,
+    /// The name of the column in the header row
+    pub column: String,
+}
+
+/// The column mapping given by the user is not valid.
+#[derive(Debug, thiserror::Error)]
+#[error("`{0}` is not a valid column mapping, expected `KEY=COLUMN`")]
+pub struct InvalidColumnMapping(pub String);
+
+impl FromStr for ColumnMapping {
+    type Err = InvalidColumnMapping;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.split_once('=') {
+            Some((key, column)) if !key.trim().is_empty() && !column.trim().is_empty() => {
+                Ok(Self {
+                    key: key.trim().to_owned(),
+                    column: column.trim().to_owned(),
+                })
+            }
+            _ => Err(InvalidColumnMapping(input.to_owned())),
+        }
+    }
+}
+
+/// Options for the CSV passes
+#[derive(Default, Clone)]
+pub struct Options {
+    /// Columns that are not named after the setting they hold. Other columns are used as is,
+    /// `description` holding the event description.
+    pub columns: Vec<ColumnMapping>,
+    /// Field delimiter. If not set, `,` will be used.
+    pub delimiter: Option<u8>,
+}
+
+impl Options {
+    /// Name of the setting held by a column
+    fn key_of<'a>(&'a self, column: &'a str) -> &'a str {
+        self.columns
+            .iter()
+            .find(|m| m.column == column)
+            .map_or(column, |m| m.key.as_str())
+    }
+
+    /// Name of the column holding a setting
+    fn column_of<'a>(&'a self, key: &'a str) -> &'a str {
+        self.columns
+            .iter()
+            .find(|m| m.key == key)
+            .map_or(key, |m| m.column.as_str())
+    }
+}
+
+/// Error occurring when reading or writing CSV.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The document is not valid CSV
+    #[error(transparent)]
+    Csv(#[from] ::csv::Error),
+    /// A row does not describe a valid event
+    #[error("row {row}: {source}")]
+    InvalidRow {
+        /// The row number, as shown by spreadsheet software (the header is row 1)
+        row: usize,
+        /// The underlying error
+        #[source]
+        source: ParsingError,
+    },
+    /// The output is not valid UTF-8
+    #[error(transparent)]
+    Utf8(#[from] std::string::FromUtf8Error),
+    /// The output could not be flushed
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+}
+
+/// Settings that are written first, in this order, when exporting
+const COLUMNS: [&str; 8] = [
+    "title",
+    "date",
+    "duration",
+    "type",
+    "speakers",
+    "lang",
+    "tags",
+    "description",
+];
+
+impl CompilingPass<&str, Options> for Import {
+    type Residual = Vec<Event>;
+    type Error = Error;
+
+    fn apply(csv: &str) -> Result<Self::Residual, Self::Error> {
+        Self::apply_with(
+            csv,
+            Options {
+                columns: Vec::new(),
+                delimiter: None,
+            },
+        )
+    }
+
+    fn apply_with(csv: &str, options: Options) -> Result<Self::Residual, Self::Error> {
+        let mut reader = ::csv::ReaderBuilder::new()
+            .delimiter(options.delimiter.unwrap_or(b','))
+            .flexible(true)
+            .from_reader(csv.as_bytes());
+        let keys: Vec<String> = reader
+            .headers()?
+            .iter()
+            .map(|column| options.key_of(column.trim()).to_owned())
+            .collect();
+
+        let mut events = Vec::new();
+        for (index, record) in reader.records().enumerate() {
+            let row = index + 2;
+            let record = record?;
+            if record.iter().all(|cell| cell.trim().is_empty()) {
+                continue;
+            }
+
+            let mut raw = RawEvent {
+                line: row,
+                last_line: row,
+                ..RawEvent::default()
+            };
+            for (key, cell) in keys.iter().zip(record.iter()) {
+                let cell = cell.trim();
+                if cell.is_empty() {
+                    continue;
+                }
+                if key == "description" {
+                    raw.description = Some(cell.to_owned());
+                } else {
+                    // Cells are literal text, only list separators keep their meaning
+                    raw.settings.push(Setting {
+                        key: key.clone(),
+                        value: cell.replace('\\', r"\\"),
+                        line: row,
+                    });
+                }
+            }
+
+            // Rows are used as lines, which would repeat the row number
+            events.push(Event::try_from(&raw).map_err(|source| Error::InvalidRow {
+                row,
+                source: source.without_line(),
+            })?);
+        }
+        Ok(events)
+    }
+}
+
+impl CompilingPass<Vec<Event>, Options> for Export {
+    type Residual = String;
+    type Error = Error;
+
+    fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
+        Self::apply_with(
+            events,
+            Options {
+                columns: Vec::new(),
+                delimiter: None,
+            },
+        )
+    }
+
+    fn apply_with(events: Vec<Event>, options: Options) -> Result<Self::Residual, Self::Error> {
+        let title_keys: BTreeSet<String> = events
+            .iter()
+            .flat_map(|e| e.titles.keys())
+            .map(|l| format!("title.{}", language::code(*l)))
+            .collect();
+        let extra_keys: BTreeSet<&String> = events.iter().flat_map(|e| e.extra.keys()).collect();
+        let keys: Vec<&str> = COLUMNS
+            .into_iter()
+            .chain(title_keys.iter().map(String::as_str))
+            .chain(extra_keys.into_iter().map(String::as_str))
+            .collect();
+
+        let mut writer = ::csv::WriterBuilder::new()
+            .delimiter(options.delimiter.unwrap_or(b','))
+            .from_writer(Vec::new());
+        writer.write_record(keys.iter().map(|k| options.column_of(k)))?;
+        for e in &events {
+            writer.write_record(keys.iter().map(|k| match *k {
+                // Translations are kept in the same cell, after their language tags
+                "description" => e.tagged_description().unwrap_or_default(),
+                _ => e.field(k).unwrap_or_default(),
+            }))?;
+        }
+
+        let bytes = writer
+            .into_inner()
+            .map_err(::csv::IntoInnerError::into_error)?;
+        Ok(String::from_utf8(bytes)?)
+    }
+}
+
+#[test]
+fn test_import() {
+    let csv = "Talk title,date,duration,speakers,room\n\
+               \"Hello, world\",2023-04-27 10:00,30,\"alice, bob\",A\n\
+               Broken,2023-04-27 11:00,thirty,,B\n";
+    let options = || Options {
+        columns: vec![ColumnMapping::from_str("title=Talk title").unwrap()],
+        delimiter: None,
+    };
+
+    let error = Import::apply_with(csv, options()).unwrap_err();
+    assert!(matches!(error, Error::InvalidRow { row: 3, .. }));
+    assert_eq!(
+        error.to_string(),
+        "row 3: could not parse duration: `invalid digit found in string`"
+    );
+
+    let events = Import::apply_with(
+        csv.lines().take(2).collect::<Vec<_>>().join("\n").as_str(),
+        options(),
+    )
+    .unwrap();
+    assert_eq!(events[0].title, "Hello, world");
+    assert_eq!(events[0].speakers, vec!["alice", "bob"]);
+    assert_eq!(events[0].field("room").as_deref(), Some("A"));
+}
@@ -0,0 +1,110 @@
+//! Selection of events by tags and fields
+
+use std::str::FromStr;
+
+use crate::{event::Event, passes::CompilingPass};
+
+/// Keeps only the events matching every given predicate
+pub struct Pass {}
+
+/// A condition on an event
+///
+/// Written as `tag:NAME` to match a tag, `KEY=VALUE` to match the value of a setting, or `KEY` to
+/// match events where the setting is present. Any of these can be negated with a leading `!`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Predicate {
+    /// The event has the given tag
+    Tag(String),
+    /// The setting is present and not empty
+    Has(String),
+    /// The setting has the given value (case insensitive)
+    Equals(String, String),
+    /// The inner predicate does not match
+    Not(Box<Self>),
+}
+
+/// The predicate given by the user is not valid.
+#[derive(Debug, thiserror::Error)]
+#[error("`{0}` is not a valid filter, expected `tag:NAME`, `KEY=VALUE` or `KEY`")]
+pub struct InvalidPredicate(pub String);
+
+impl FromStr for Predicate {
+    type Err = InvalidPredicate;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        if let Some(negated) = input.strip_prefix('!') {
+            return Ok(Self::Not(Box::new(Self::from_str(negated)?)));
+        }
+        if let Some(tag) = input.strip_prefix("tag:") {
+            return Ok(Self::Tag(tag.trim().to_owned()));
+        }
+        match input.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                Ok(Self::Equals(key.trim().to_owned(), value.trim().to_owned()))
+            }
+            None if !input.is_empty() => Ok(Self::Has(input.to_owned())),
+            _ => Err(InvalidPredicate(input.to_owned())),
+        }
+    }
+}
+
+impl Predicate {
+    /// Check whether the event matches
+    #[must_use]
+    pub fn matches(&self, event: &Event) -> bool {
+        match self {
+            Self::Tag(tag) => event.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)),
+            Self::Has(key) => event.field(key).is_some_and(|v| !v.is_empty()),
+            Self::Equals(key, value) => event
+                .field(key)
+                .is_some_and(|v| v.eq_ignore_ascii_case(value)),
+            Self::Not(inner) => !inner.matches(event),
+        }
+    }
+}
+
+/// Options for the filter pass
+#[derive(Default, Clone)]
+pub struct Options {
+    /// Predicates an event must all match to be kept
+    pub predicates: Vec<Predicate>,
+}
+
+impl CompilingPass<Vec<Event>, Options> for Pass {
+    type Residual = Vec<Event>;
+    type Error = std::convert::Infallible;
+
+    fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
+        Ok(events)
+    }
+
+    fn apply_with(events: Vec<Event>, options: Options) -> Result<Self::Residual, Self::Error> {
+        Ok(events
+            .into_iter()
+            .filter(|e| options.predicates.iter().all(|p| p.matches(e)))
+            .collect())
+    }
+}
+
+#[test]
+fn test_predicates() {
+    let event = Event::from_str(
+        "title: Rust for beginners\ndate: 2023-04-27 10:00\nduration: 60\ntags: [rust, workshop]\nlevel: beginner",
+    )
+    .unwrap();
+
+    assert_eq!(
+        event.extra.get("level").map(String::as_str),
+        Some("beginner")
+    );
+    assert!(Predicate::from_str("tag:rust").unwrap().matches(&event));
+    assert!(Predicate::from_str("level=Beginner")
+        .unwrap()
+        .matches(&event));
+    assert!(Predicate::from_str("type=talk").unwrap().matches(&event));
+    assert!(!Predicate::from_str("!tag:workshop")
+        .unwrap()
+        .matches(&event));
+    assert!(!Predicate::from_str("sponsor").unwrap().matches(&event));
+}
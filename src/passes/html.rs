@@ -1,12 +1,15 @@
 //! HTML backend
 use crate::{
-    event::{find_bounding_box, Event, InvalidDatetime, Type},
+    event::{end_hour, find_bounding_box, Event, InvalidDatetime, Type},
+    language::{self, Flags},
+    locale::Locale,
+    markup::{Block, Inline, Markup},
     passes::CompilingPass,
     templating::{replace, Error},
 };
-use chrono::{Datelike, Duration};
+use chrono::{DateTime, Duration, Local, NaiveDate, Timelike};
 use isolang::Language;
-use std::str::FromStr;
+use std::{fmt::Write, str::FromStr};
 use thiserror::Error;
 
 /// Backend outputing events to a standalone HTML document containing a timetable
@@ -18,9 +21,14 @@ pub trait ToHTML {
     fn to_html(&self) -> String;
 }
 /// Options for the HTML backend
+#[derive(Default, Clone)]
 pub struct HTMLBackendOptions {
     /// Path to the template file. If not set, the default template (`data/template.html`) will be used.
     pub template_path: Option<String>,
+    /// Language of the dates, of the page and of the hint above the calendar
+    pub locale: Locale,
+    /// Flags shown instead of the codes of the languages of the talks
+    pub flags: Flags,
 }
 
 /// Error that can occur during the compilation of the HTML backend
@@ -50,49 +58,299 @@ fn get_template(template_path: Option<String>) -> Result<String, std::io::Error>
 
 impl ToHTML for Event {
     fn to_html(&self) -> String {
-        let duration = self.duration * 100 / (8 * 60);
-        let class = self.event_type.to_string();
-        // Create a div for the event
-        let mut res = format!("\t<div class=\"event {class}\" style=\"height: {duration}%;\">");
+        event_to_html(self, &Flags::default(), "")
+    }
+}
+
+/// The end of an event
+fn end(e: &Event) -> DateTime<Local> {
+    e.start_date + Duration::minutes(i64::from(e.duration))
+}
+
+/// Format the time range of an event, such as `10:10–10:40`
+fn time_range(e: &Event) -> String {
+    let start = e.start_date.format("%H:%M");
+    if e.duration == 0 {
+        start.to_string()
+    } else {
+        format!("{start}–{}", end(e).format("%H:%M"))
+    }
+}
+
+/// Show an event, with the flags given for its languages and the given inline style
+fn event_to_html(e: &Event, flags: &Flags, style: &str) -> String {
+    let mut class = e.event_type.to_string();
+    for tag in &e.tags {
+        let _ = write!(class, " tag-{}", slug(tag));
+    }
+    // Expose custom settings to the template styles and scripts
+    let mut data = String::new();
+    if !e.tags.is_empty() {
+        let _ = write!(data, " data-tags=\"{}\"", escape(&e.tags.join(",")));
+    }
+    for (key, value) in &e.extra {
+        let _ = write!(data, " data-{}=\"{}\"", slug(key), escape(value));
+    }
+    // Create a div for the event
+    let mut res = format!("\t<div class=\"event {class}\"{data} style=\"{style}\">");
+    let _ = write!(res, "<div class=\"time\">{}</div>", time_range(e));
 
-        // Display the title and author of the event
-        res += "<div class=\"title\">";
+    // Display the title and author of the event
+    res += "<div class=\"title\">";
+    for language in &e.languages {
+        res += &language_badge(*language, flags);
+        res += " ";
+    }
+    let _ = write!(res, "<b>{}</b><br>", escape(&e.title));
+    if e.event_type == Type::Talk && !e.speakers.is_empty() {
+        let _ = write!(res, "<span>{}</span>", escape(&e.speakers_string()));
+    }
+    res += "</div>\n";
+
+    // Display the abstract of the event
+    if let Some(description) = &e.description {
         res += format!(
-            "{}<b>{}</b><br>",
-            self.language
-                .map_or_else(String::new, |l| l.to_html() + " "),
-            self.title
+            "<div class=\"abstract\">{}</div>",
+            Markup::parse(description).to_html()
         )
         .as_str();
-        if self.event_type == Type::Talk && !self.speakers.is_empty() {
-            res += "<span>";
-            res += &self.speakers_string();
-            res += "</span>";
+    }
+    res += "</div>";
+
+    res
+}
+
+/// Minutes in a row of the calendar grid
+const SLOT_MINUTES: u32 = 5;
+
+/// Rows of the calendar grid in an hour
+const SLOTS_PER_HOUR: u32 = 60 / SLOT_MINUTES;
+
+/// The rows of the calendar, shared by all the days
+struct Grid {
+    /// Hour of the first row
+    first_hour: u32,
+    /// Number of rows
+    slots: u32,
+}
+
+impl Grid {
+    /// The rows of an event, as its first row (starting at 1) and its number of rows
+    fn rows(&self, e: &Event) -> (u32, u32) {
+        let time = e.start_date.time();
+        let minutes = (time.hour() * 60 + time.minute()).saturating_sub(self.first_hour * 60);
+        let row = (minutes / SLOT_MINUTES).min(self.slots - 1) + 1;
+        let span = e
+            .duration
+            .div_ceil(SLOT_MINUTES)
+            .clamp(1, self.slots - row + 1);
+        (row, span)
+    }
+
+    /// The rows of the grid, with a line at each hour
+    fn grid_style(&self, lanes: usize) -> String {
+        format!(
+            "grid-template-rows: repeat({}, var(--slot-height)); grid-template-columns: repeat({lanes}, 1fr);",
+            self.slots
+        )
+    }
+
+    /// The column of hours, at the left of the days
+    fn hours(&self, last_hour: u32) -> String {
+        let mut r = format!(
+            "<div class=\"hours\"><h2></h2><div class=\"slots\" style=\"{}\">",
+            self.grid_style(1)
+        );
+        for (i, hour) in (self.first_hour..last_hour).enumerate() {
+            let row = u32::try_from(i).unwrap_or_default() * SLOTS_PER_HOUR + 1;
+            let _ = write!(
+                r,
+                "<div class=\"hour\" style=\"grid-row: {row} / span {SLOTS_PER_HOUR};\">{:02}:00</div>",
+                hour % 24
+            );
         }
-        res += "</div>\n";
-
-        // Display the abstract of the event
-        if let Some(description) = &self.description {
-            res += format!(
-                "<div class=\"abstract\"><p>{}</p></div>",
-                description.replace("\n\n", "</p><p>")
-            )
-            .as_str();
+        r += "</div></div>\n";
+        r
+    }
+
+    /// The column of a day, with its events side by side when they overlap
+    fn day(&self, day: NaiveDate, events: &[&Event], flags: &Flags, locale: Locale) -> String {
+        let (lanes, lane_count) = lanes(events);
+        let mut r = format!(
+            "<section class=\"day\"><h2>{}</h2><div class=\"slots\" style=\"{}\">",
+            locale.day(&day),
+            self.grid_style(lane_count)
+        );
+        // Empty hours are shown, so that gaps between events are visible
+        for row in (1..=self.slots).step_by(SLOTS_PER_HOUR as usize) {
+            let _ = write!(
+                r,
+                "<div class=\"slot\" style=\"grid-row: {row} / span {SLOTS_PER_HOUR};\"></div>"
+            );
+        }
+        r += "\n";
+        for (e, (lane, end)) in events.iter().zip(lanes) {
+            let (row, span) = self.rows(e);
+            let style = format!(
+                "grid-row: {row} / span {span}; grid-column: {} / {};",
+                lane + 1,
+                end + 1
+            );
+            r += &event_to_html(e, flags, &style);
+            r += "\n";
         }
-        res += "</div>";
+        r += "</div></section>\n";
+        r
+    }
+}
 
-        res
+/// Place events sorted by start in lanes, so that overlapping events are side by side
+///
+/// Each event gets its lane and the lane where it stops, as far right as possible without
+/// covering an overlapping event. The number of lanes is returned too.
+fn lanes(events: &[&Event]) -> (Vec<(usize, usize)>, usize) {
+    // Short events take at least a row
+    let span = |e: &Event| {
+        let minutes = e.duration.max(SLOT_MINUTES);
+        (
+            e.start_date,
+            e.start_date + Duration::minutes(i64::from(minutes)),
+        )
+    };
+    let overlap = |a: &Event, b: &Event| {
+        let ((a_start, a_end), (b_start, b_end)) = (span(a), span(b));
+        a_start < b_end && b_start < a_end
+    };
+
+    let mut lane_ends: Vec<DateTime<Local>> = Vec::new();
+    let mut lanes = Vec::with_capacity(events.len());
+    for e in events {
+        let (start, end) = span(e);
+        let free = lane_ends.iter().position(|lane_end| *lane_end <= start);
+        let lane = free.unwrap_or_else(|| {
+            lane_ends.push(end);
+            lane_ends.len() - 1
+        });
+        lane_ends[lane] = end;
+        lanes.push(lane);
     }
+
+    let count = lane_ends.len().max(1);
+    let placed = lanes
+        .iter()
+        .enumerate()
+        .map(|(i, &lane)| {
+            let end = lanes
+                .iter()
+                .enumerate()
+                .filter(|&(j, &other)| other > lane && overlap(events[i], events[j]))
+                .map(|(_, &other)| other)
+                .min()
+                .unwrap_or(count);
+            (lane, end)
+        })
+        .collect();
+    (placed, count)
 }
 
-impl ToHTML for Language {
+/// Escape the characters that have a special meaning in HTML
+#[must_use]
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Turn an arbitrary string into something usable in class and attribute names
+fn slug(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+impl ToHTML for Markup {
+    fn to_html(&self) -> String {
+        self.0.to_html()
+    }
+}
+
+impl<T: ToHTML> ToHTML for Vec<T> {
+    fn to_html(&self) -> String {
+        self.iter().map(ToHTML::to_html).collect()
+    }
+}
+
+fn list_items(items: &[Vec<Block>]) -> String {
+    items.iter().fold(String::new(), |mut r, item| {
+        let _ = write!(r, "<li>{}</li>", item.to_html());
+        r
+    })
+}
+
+impl ToHTML for Block {
     fn to_html(&self) -> String {
         match self {
-            Self::Fra => "🇫🇷",
-            Self::Eng => "🇬🇧",
-            _ => "?",
+            Self::Paragraph(content) => format!("<p>{}</p>", content.to_html()),
+            // Headings are shifted, since the page and the days already use `<h1>` and `<h2>`
+            Self::Heading(level, content) => {
+                let level = (level + 2).min(6);
+                format!("<h{level}>{}</h{level}>", content.to_html())
+            }
+            Self::List(None, items) => format!("<ul>{}</ul>", list_items(items)),
+            Self::List(Some(start), items) => {
+                format!("<ol start=\"{start}\">{}</ol>", list_items(items))
+            }
+            Self::Quote(content) => format!("<blockquote>{}</blockquote>", content.to_html()),
+            Self::Code(code) => format!("<pre><code>{}</code></pre>", escape(code)),
+            Self::Rule => "<hr>".to_owned(),
+        }
+    }
+}
+
+impl ToHTML for Inline {
+    fn to_html(&self) -> String {
+        match self {
+            Self::Text(text) => escape(text),
+            Self::Emphasis(content) => format!("<em>{}</em>", content.to_html()),
+            Self::Strong(content) => format!("<strong>{}</strong>", content.to_html()),
+            Self::Code(code) => format!("<code>{}</code>", escape(code)),
+            Self::Link(url, content) => {
+                format!("<a href=\"{}\">{}</a>", escape(url), content.to_html())
+            }
+            // Math is left for a client-side renderer such as KaTeX or MathJax
+            Self::Math(math) => format!("<span class=\"math\">\\({}\\)</span>", escape(math)),
+            Self::DisplayMath(math) => {
+                format!("<span class=\"math display\">\\[{}\\]</span>", escape(math))
+            }
+            Self::SoftBreak => "\n".to_owned(),
+            Self::HardBreak => "<br>".to_owned(),
         }
-        .to_owned()
+    }
+}
+
+/// Show a language as its flag if one is given, or as a badge with its code
+fn language_badge(l: Language, flags: &Flags) -> String {
+    let (class, text) = flags.get(l).map_or_else(
+        || ("lang", language::code(l).to_uppercase()),
+        |flag| ("lang flag", flag.to_owned()),
+    );
+    format!(
+        "<span class=\"{class}\" title=\"{}\">{}</span>",
+        escape(l.to_name()),
+        escape(&text)
+    )
+}
+
+impl ToHTML for Language {
+    fn to_html(&self) -> String {
+        language_badge(*self, &Flags::default())
     }
 }
 
@@ -100,12 +358,7 @@ impl CompilingPass<Vec<Event>> for HTMLBackend {
     type Residual = String;
     type Error = HTMLBackendCompilationError;
     fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
-        Self::apply_with(
-            events,
-            HTMLBackendOptions {
-                template_path: None,
-            },
-        )
+        Self::apply_with(events, HTMLBackendOptions::default())
     }
 }
 
@@ -114,55 +367,97 @@ impl CompilingPass<Vec<Event>, HTMLBackendOptions> for HTMLBackend {
     type Error = HTMLBackendCompilationError;
 
     fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
-        Self::apply_with(
-            events,
-            HTMLBackendOptions {
-                template_path: None,
-            },
-        )
+        Self::apply_with(events, HTMLBackendOptions::default())
     }
 
     fn apply_with(
-        events: Vec<Event>,
+        mut events: Vec<Event>,
         options: HTMLBackendOptions,
     ) -> Result<Self::Residual, Self::Error> {
         let template = get_template(options.template_path)?;
+        let locale = options.locale;
+        for e in &mut events {
+            if e.title.is_empty() {
+                locale.no_title().clone_into(&mut e.title);
+            }
+        }
 
-        // Find the number of days and the first day of the events
+        // Find the days and the hours spanned by the events
         let bounding_box = find_bounding_box(&events).ok_or(InvalidDatetime {})?;
-        let nb_days = bounding_box.last_day()?.day() - bounding_box.first_day().unwrap().day() + 1;
         let first_day = bounding_box.first_day()?.date_naive();
+        // Events ending at midnight end on the day they start
+        let last_day = events
+            .iter()
+            .map(|e| e.start_date.date_naive())
+            .max()
+            .unwrap_or(first_day);
+        let first_hour = bounding_box.up_left.hour();
+        let last_hour = end_hour(&events).max(first_hour + 1);
+        let grid = Grid {
+            first_hour,
+            slots: (last_hour - first_hour) * SLOTS_PER_HOUR,
+        };
 
-        let mut str = String::new();
-        // Create a div for each day, containing the event of the day
-        for i in 0..nb_days {
-            let curr_day = first_day + Duration::days(i64::from(i));
+        let mut str = grid.hours(last_hour);
+        // Create a column for each day, containing the events of the day
+        for day in first_day.iter_days().take_while(|d| *d <= last_day) {
             let mut day_events: Vec<&Event> = events
                 .iter()
-                .filter(|ev| ev.start_date.date_naive() == curr_day)
+                .filter(|ev| ev.start_date.date_naive() == day)
                 .collect();
             day_events.sort_by_key(|e| e.start_date.time());
-
-            str += "<div class=\"day\">";
-            str.push_str(format!("<h2>{}</h2>", curr_day.format("%A, %B %e")).as_str());
-            let mut previous_hour = None;
-            for event in day_events {
-                // Display the start time, if needed
-                if previous_hour.is_none() || previous_hour.unwrap() == event.start_date {
-                    // Display the end time
-                    str += event.start_date.format("%H:%M").to_string().as_str();
-                    previous_hour = Some(event.start_date);
-                }
-                // Display the event
-                str += event.to_html().as_str();
-                // Display the end time
-                str += (event.start_date + Duration::minutes(i64::from(event.duration)))
-                    .format("%H:%M")
-                    .to_string()
-                    .as_str();
-            }
-            str += "</div>";
+            str += &grid.day(day, &day_events, &options.flags, locale);
         }
-        Ok(replace(&template, "CALENDAR", &str)?)
+        let page = replace(&template, "LANG", locale.name())?;
+        let page = replace(&page, "HINT", locale.show_descriptions())?;
+        Ok(replace(&page, "CALENDAR", &str)?)
     }
 }
+
+#[test]
+fn test_calendar_grid() {
+    let events = crate::passes::parser::ParseTimetable::apply(
+        "title: Welcome\ndate: 2023-04-30 09:00\nduration: 30\n---\ntitle: Workshop\ndate: 2023-04-30 10:00\nduration: 60\n---\ntitle: Talk\ndate: 2023-04-30 10:30\nduration: 30\n---\ntitle: Closing\ndate: 2023-05-01 11:55\nduration: 5",
+    )
+    .unwrap();
+    let html = <HTMLBackend as CompilingPass<Vec<Event>>>::apply(events).unwrap();
+
+    // The days span two months, and the rows span 9:00 to 12:00
+    assert!(html.contains("<h2>Sunday, April 30</h2>"));
+    assert!(html.contains("<h2>Monday, May 1</h2>"));
+    assert!(html.contains(">11:00</div>") && !html.contains(">12:00</div>"));
+    // The gap between the welcome and the workshop is kept, and overlapping events are side by
+    // side
+    assert!(html.contains("grid-row: 1 / span 6; grid-column: 1 / 3;"));
+    assert!(html.contains("grid-row: 13 / span 12; grid-column: 1 / 2;"));
+    assert!(html.contains("grid-row: 19 / span 6; grid-column: 2 / 3;"));
+    assert!(html.contains("grid-row: 36 / span 1; grid-column: 1 / 2;"));
+}
+
+#[test]
+fn test_calendar_grid_midnight() {
+    let events = crate::passes::parser::ParseTimetable::apply(
+        "title: Concert\ndate: 2023-04-30 22:00\nduration: 60\n---\ntitle: Party\ndate: 2023-04-30 23:00\nduration: 60",
+    )
+    .unwrap();
+    let html = <HTMLBackend as CompilingPass<Vec<Event>>>::apply(events).unwrap();
+
+    // An event ending at midnight keeps its last hour, and does not add the next day
+    assert!(html.contains(">23:00</div>"));
+    assert!(html.contains("grid-row: 13 / span 12;"));
+    assert!(!html.contains("<h2>Monday, May 1</h2>"));
+}
+
+#[test]
+fn test_escape_event() {
+    let events = crate::passes::parser::ParseTimetable::apply(
+        "title: R&D <intro>\ndate: 2023-04-30 09:00\nduration: 30\nspeakers: <b>Eve</b>, \"Mallory\"\ntags: a\"b\nroom: <hall>",
+    )
+    .unwrap();
+    let html = <HTMLBackend as CompilingPass<Vec<Event>>>::apply(events).unwrap();
+
+    assert!(html.contains("<b>R&amp;D &lt;intro&gt;</b>"));
+    assert!(html.contains("<span>&lt;b&gt;Eve&lt;/b&gt;, &quot;Mallory&quot;</span>"));
+    assert!(html.contains("data-tags=\"a&quot;b\"") && html.contains("data-room=\"&lt;hall&gt;\""));
+    assert!(!html.contains("<intro>") && !html.contains("<hall>"));
+}
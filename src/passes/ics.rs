@@ -0,0 +1,544 @@
+//! iCalendar (`.ics`) input pass
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{
+    DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday,
+};
+
+use crate::{
+    event::{Event, Recurrence, Type},
+    language,
+    passes::CompilingPass,
+};
+
+/// Reads the `VEVENT`s of an iCalendar document
+pub struct Import {}
+
+/// Error occurring when reading an iCalendar document.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A date could not be read
+    #[error("line {line}: `{value}` is not a valid iCalendar date")]
+    InvalidDate {
+        /// The line of the property
+        line: usize,
+        /// The value of the property
+        value: String,
+    },
+    /// A duration could not be read
+    #[error("line {line}: `{value}` is not a valid iCalendar duration")]
+    InvalidDuration {
+        /// The line of the property
+        line: usize,
+        /// The value of the property
+        value: String,
+    },
+    /// A recurrence rule cannot be expressed as a Seri recurrence
+    #[error("line {line}: the recurrence `{value}` is not supported")]
+    UnsupportedRecurrence {
+        /// The line of the property
+        line: usize,
+        /// The value of the property
+        value: String,
+    },
+    /// An event has no start date
+    #[error("line {line}: the event has no `DTSTART`")]
+    MissingStart {
+        /// The line where the event begins
+        line: usize,
+    },
+    /// An event is not closed by `END:VEVENT`
+    #[error("line {line}: the event is never closed")]
+    UnclosedEvent {
+        /// The line where the event begins
+        line: usize,
+    },
+}
+
+/// A content line, once unfolded
+struct Property {
+    /// The line where the property starts, starting at 1
+    line: usize,
+    /// The property name, in upper case
+    name: String,
+    /// The parameters, with names in upper case
+    parameters: BTreeMap<String, String>,
+    /// The raw value
+    value: String,
+}
+
+/// Unfold the content lines and split them into properties
+fn properties(ics: &str) -> Vec<Property> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for (index, line) in ics.lines().enumerate() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some((_, previous))) => *previous += continuation,
+            _ => lines.push((index + 1, line.to_owned())),
+        }
+    }
+
+    lines
+        .into_iter()
+        .filter_map(|(line, content)| {
+            // The value starts at the first colon that is not in a quoted parameter value
+            let mut quoted = false;
+            let colon = content.char_indices().find_map(|(i, c)| match c {
+                '"' => {
+                    quoted = !quoted;
+                    None
+                }
+                ':' if !quoted => Some(i),
+                _ => None,
+            })?;
+            let mut parts = content[..colon].split(';');
+            let name = parts.next()?.to_uppercase();
+            let parameters = parts
+                .filter_map(|p| p.split_once('='))
+                .map(|(k, v)| (k.to_uppercase(), v.trim_matches('"').to_owned()))
+                .collect();
+            Some(Property {
+                line,
+                name,
+                parameters,
+                value: content[colon + 1..].to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// Remove the escapes of a `TEXT` value
+fn unescape_text(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n' | 'N') => res.push('\n'),
+                Some(other) => res.push(other),
+                None => (),
+            }
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+/// Split a `TEXT` list on unescaped commas
+fn split_text_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                item.push(c);
+                item.extend(chars.next());
+            }
+            ',' => items.push(std::mem::take(&mut item)),
+            _ => item.push(c),
+        }
+    }
+    items.push(item);
+    items
+        .iter()
+        .map(|i| unescape_text(i.trim()))
+        .filter(|i| !i.is_empty())
+        .collect()
+}
+
+/// Read a `DATE` or `DATE-TIME` value. Floating times and times with a `TZID` are read as local
+/// times, UTC times are converted to local times.
+fn parse_date(property: &Property) -> Result<DateTime<Local>, Error> {
+    let invalid = || Error::InvalidDate {
+        line: property.line,
+        value: property.value.clone(),
+    };
+    let value = property.value.trim();
+
+    if let Some(utc) = value.strip_suffix('Z') {
+        let naive = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").map_err(|_| invalid())?;
+        return Ok(Utc.from_utc_datetime(&naive).with_timezone(&Local));
+    }
+    let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y%m%d")
+                .map(|d| d.and_hms_opt(0, 0, 0).unwrap_or_default())
+        })
+        .map_err(|_| invalid())?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(invalid)
+}
+
+/// Read a `DURATION` value such as `PT1H30M` or `P1D`, in minutes
+fn parse_duration(property: &Property) -> Result<u32, Error> {
+    let invalid = || Error::InvalidDuration {
+        line: property.line,
+        value: property.value.clone(),
+    };
+    let value = property.value.trim().trim_start_matches('+');
+    let value = value.strip_prefix('P').ok_or_else(invalid)?;
+
+    let mut minutes: u32 = 0;
+    let mut number = String::new();
+    for c in value.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => (),
+            unit => {
+                let n: u32 = number.parse().map_err(|_| invalid())?;
+                number.clear();
+                let unit: u32 = match unit {
+                    'W' => 7 * 24 * 60,
+                    'D' => 24 * 60,
+                    'H' => 60,
+                    'M' => 1,
+                    // Seconds are ignored
+                    'S' => 0,
+                    _ => return Err(invalid()),
+                };
+                minutes = n
+                    .checked_mul(unit)
+                    .and_then(|n| minutes.checked_add(n))
+                    .ok_or_else(invalid)?;
+            }
+        }
+    }
+    Ok(minutes)
+}
+
+/// Maximum number of occurrences a recurrence rule is expanded to
+const MAX_OCCURRENCES: usize = 1000;
+
+/// Read the days of a list of `DATE` or `DATE-TIME` values, as in `RDATE` and `EXDATE`
+fn parse_dates(properties: &[&Property], name: &str) -> Result<BTreeSet<NaiveDate>, Error> {
+    let mut dates = BTreeSet::new();
+    for p in properties.iter().filter(|p| p.name == name) {
+        for value in p.value.split(',') {
+            let date = value
+                .get(..8)
+                .and_then(|d| NaiveDate::parse_from_str(d, "%Y%m%d").ok())
+                .ok_or_else(|| Error::InvalidDate {
+                    line: p.line,
+                    value: value.to_owned(),
+                })?;
+            dates.insert(date);
+        }
+    }
+    Ok(dates)
+}
+
+/// Read a weekday of `BYDAY`, such as `MO`
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    Some(match day {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+/// Expand a daily or weekly `RRULE` starting on `start` into its days, `start` excluded, along
+/// with whether it is a plain rule that Seri can write as `daily until` or `weekly until`.
+///
+/// Returns [`None`] for the rules that cannot be expanded.
+fn expand_rrule(value: &str, start: NaiveDate) -> Option<(Vec<NaiveDate>, Option<Recurrence>)> {
+    let mut parts = BTreeMap::new();
+    for part in value.split(';') {
+        let (key, value) = part.split_once('=')?;
+        parts.insert(key.to_uppercase(), value.to_uppercase());
+    }
+    let weekly = match parts.remove("FREQ")?.as_str() {
+        "DAILY" => false,
+        "WEEKLY" => true,
+        _ => return None,
+    };
+    let interval: i64 = match parts.remove("INTERVAL") {
+        Some(interval) => interval.parse().ok().filter(|i| *i > 0)?,
+        None => 1,
+    };
+    let until = match parts.remove("UNTIL") {
+        Some(until) => Some(NaiveDate::parse_from_str(until.get(..8)?, "%Y%m%d").ok()?),
+        None => None,
+    };
+    let count: Option<usize> = match parts.remove("COUNT") {
+        Some(count) => Some(count.parse().ok()?),
+        None => None,
+    };
+    let weekdays: Option<Vec<Weekday>> = match parts.remove("BYDAY") {
+        Some(days) => Some(days.split(',').map(parse_weekday).collect::<Option<_>>()?),
+        None => None,
+    };
+    // Weeks start on Monday, the default `WKST`
+    if parts.remove("WKST").is_some_and(|w| w != "MO") {
+        return None;
+    }
+    // Rules without an end, or with parts such as `BYMONTH`, are not supported
+    if !parts.is_empty() || until.is_some() == count.is_some() {
+        return None;
+    }
+
+    let plain = match (&weekdays, until) {
+        (None, Some(until)) if interval == 1 => Some(if weekly {
+            Recurrence::Weekly { until }
+        } else {
+            Recurrence::Daily { until }
+        }),
+        _ => None,
+    };
+
+    // The first occurrence is `start`, which counts towards `COUNT`
+    let mut days = Vec::new();
+    // Without `BYDAY`, daily rules happen every day and weekly ones on the weekday of `start`
+    let on_weekday = |day: NaiveDate| {
+        weekdays.as_ref().map_or_else(
+            || !weekly || day.weekday() == start.weekday(),
+            |w| w.contains(&day.weekday()),
+        )
+    };
+    let step = if weekly {
+        Duration::weeks(interval)
+    } else {
+        Duration::days(interval)
+    };
+    let mut period = if weekly {
+        start - Duration::days(start.weekday().num_days_from_monday().into())
+    } else {
+        start
+    };
+    loop {
+        let candidates = if weekly { 0..7 } else { 0..1 };
+        for day in candidates.map(|offset| period + Duration::days(offset)) {
+            if day <= start || !on_weekday(day) {
+                continue;
+            }
+            if until.is_some_and(|until| day > until) || count.is_some_and(|c| days.len() + 1 >= c)
+            {
+                return Some((days, plain));
+            }
+            if days.len() >= MAX_OCCURRENCES {
+                return None;
+            }
+            days.push(day);
+        }
+        period += step;
+    }
+}
+
+/// Read the recurrence of an event starting on `start`, from its `RRULE`, `RDATE` and `EXDATE`
+fn recurrence(properties: &[&Property], start: NaiveDate) -> Result<Option<Recurrence>, Error> {
+    let rrule = properties.iter().find(|p| p.name == "RRULE");
+    let unsupported = |p: &Property| Error::UnsupportedRecurrence {
+        line: p.line,
+        value: p.value.clone(),
+    };
+
+    let (mut days, plain) = match rrule {
+        Some(rrule) => {
+            let (days, plain) =
+                expand_rrule(rrule.value.trim(), start).ok_or_else(|| unsupported(rrule))?;
+            (days.into_iter().collect(), plain)
+        }
+        None => (BTreeSet::new(), None),
+    };
+    let added = parse_dates(properties, "RDATE")?;
+    let excluded = parse_dates(properties, "EXDATE")?;
+
+    if added.is_empty() && excluded.is_empty() && plain.is_some() {
+        return Ok(plain);
+    }
+    // Seri events always happen on their start date
+    if let (true, Some(exdate)) = (
+        excluded.contains(&start),
+        properties.iter().find(|p| p.name == "EXDATE"),
+    ) {
+        return Err(unsupported(exdate));
+    }
+    days.extend(added);
+    days.remove(&start);
+    let days: Vec<NaiveDate> = days.difference(&excluded).copied().collect();
+    Ok((!days.is_empty()).then_some(Recurrence::Dates(days)))
+}
+
+/// Build an event from the properties of a `VEVENT`
+fn event(begin: usize, properties: &[&Property]) -> Result<Event, Error> {
+    let get = |name: &str| properties.iter().find(|p| p.name == name);
+
+    let start_date = parse_date(get("DTSTART").ok_or(Error::MissingStart { line: begin })?)?;
+    let duration = if let Some(end) = get("DTEND") {
+        let minutes = (parse_date(end)? - start_date).num_minutes();
+        u32::try_from(minutes).unwrap_or_default()
+    } else if let Some(duration) = get("DURATION") {
+        parse_duration(duration)?
+    } else {
+        0
+    };
+
+    let summary = get("SUMMARY");
+    // The language tag of the summary, such as `fr-FR`, starts with an ISO 639 code
+    let languages = summary
+        .and_then(|s| s.parameters.get("LANGUAGE"))
+        .and_then(|l| language::parse(l.split('-').next()?))
+        .into_iter()
+        .collect();
+
+    let mut extra = BTreeMap::new();
+    if let Some(location) = get("LOCATION") {
+        extra.insert("room".to_owned(), unescape_text(&location.value));
+    }
+    if let Some(url) = get("URL") {
+        extra.insert("url".to_owned(), url.value.clone());
+    }
+
+    let recurrence = recurrence(properties, start_date.date_naive())?;
+
+    Ok(Event {
+        event_type: Type::Talk,
+        title: summary.map(|s| unescape_text(&s.value)).unwrap_or_default(),
+        start_date,
+        duration,
+        description: get("DESCRIPTION")
+            .map(|d| unescape_text(&d.value).trim().to_owned())
+            .filter(|d| !d.is_empty()),
+        titles: BTreeMap::new(),
+        descriptions: BTreeMap::new(),
+        languages,
+        speakers: Vec::new(),
+        recurrence,
+        tags: properties
+            .iter()
+            .filter(|p| p.name == "CATEGORIES")
+            .flat_map(|p| split_text_list(&p.value))
+            .collect(),
+        extra,
+    })
+}
+
+impl CompilingPass<&str> for Import {
+    type Residual = Vec<Event>;
+    type Error = Error;
+
+    fn apply(ics: &str) -> Result<Self::Residual, Self::Error> {
+        let properties = properties(ics);
+        let mut events = Vec::new();
+        // Properties of the current event, and the line where it begins
+        let mut current: Option<(usize, Vec<&Property>)> = None;
+        // Nested components (alarms) are skipped
+        let mut depth = 0;
+
+        for p in &properties {
+            match (p.name.as_str(), p.value.trim().to_uppercase().as_str()) {
+                ("BEGIN", "VEVENT") if current.is_none() => current = Some((p.line, Vec::new())),
+                ("BEGIN", _) if current.is_some() => depth += 1,
+                ("END", "VEVENT") if depth == 0 => {
+                    if let Some((begin, event_properties)) = current.take() {
+                        events.push(event(begin, &event_properties)?);
+                    }
+                }
+                ("END", _) if current.is_some() => depth -= 1,
+                _ => {
+                    if let (Some((_, event_properties)), 0) = (&mut current, depth) {
+                        event_properties.push(p);
+                    }
+                }
+            }
+        }
+
+        match current {
+            Some((line, _)) => Err(Error::UnclosedEvent { line }),
+            None => Ok(events),
+        }
+    }
+}
+
+#[test]
+fn test_import() {
+    use isolang::Language;
+
+    let ics = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nSUMMARY;LANGUAGE=fr:Caf\r\n é\\, thé\r\nDTSTART:20230427T100000\r\nDURATION:PT1H30M\r\nRRULE:FREQ=DAILY;UNTIL=20230430T000000Z\r\nBEGIN:VALARM\r\nDESCRIPTION:Reminder\r\nEND:VALARM\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+    let events = Import::apply(ics).unwrap();
+
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].title, "Café, thé");
+    assert_eq!(events[0].duration, 90);
+    assert_eq!(events[0].languages, vec![Language::Fra]);
+    assert_eq!(events[0].description, None);
+    assert_eq!(
+        events[0].recurrence,
+        Some(Recurrence::Daily {
+            until: NaiveDate::from_ymd_opt(2023, 4, 30).unwrap()
+        })
+    );
+
+    assert!(matches!(
+        Import::apply("BEGIN:VEVENT\nSUMMARY:a\nEND:VEVENT"),
+        Err(Error::MissingStart { line: 1 })
+    ));
+    assert!(matches!(
+        Import::apply("BEGIN:VEVENT\nDTSTART:20230427T100000\nDURATION:P9999999W\nEND:VEVENT"),
+        Err(Error::InvalidDuration { line: 3, .. })
+    ));
+}
+
+#[test]
+fn test_import_recurrence() {
+    let recurrence = |rules: &str| {
+        let ics = format!("BEGIN:VEVENT\nDTSTART:20230427T100000\n{rules}\nEND:VEVENT");
+        Import::apply(&ics).map(|events| events[0].recurrence.clone())
+    };
+    let dates = |dates: &[&str]| {
+        Some(Some(Recurrence::Dates(
+            dates.iter().map(|d| d.parse().unwrap()).collect(),
+        )))
+    };
+
+    // 2023-04-27 is a Thursday
+    assert_eq!(
+        recurrence("RRULE:FREQ=WEEKLY;UNTIL=20230511").ok(),
+        Some(Some(Recurrence::Weekly {
+            until: NaiveDate::from_ymd_opt(2023, 5, 11).unwrap()
+        }))
+    );
+    assert_eq!(
+        recurrence("RRULE:FREQ=DAILY;COUNT=3").ok(),
+        dates(&["2023-04-28", "2023-04-29"])
+    );
+    assert_eq!(
+        recurrence("RRULE:FREQ=WEEKLY;BYDAY=TU,TH;COUNT=4").ok(),
+        dates(&["2023-05-02", "2023-05-04", "2023-05-09"])
+    );
+    assert_eq!(
+        recurrence("RRULE:FREQ=WEEKLY;INTERVAL=2;UNTIL=20230530T000000Z").ok(),
+        dates(&["2023-05-11", "2023-05-25"])
+    );
+    assert_eq!(
+        recurrence("RRULE:FREQ=DAILY;UNTIL=20230501\nEXDATE:20230429T100000,20230430T100000\nRDATE;VALUE=DATE:20230601").ok(),
+        dates(&["2023-04-28", "2023-05-01", "2023-06-01"])
+    );
+    assert_eq!(
+        recurrence("RDATE:20230501T100000").ok(),
+        dates(&["2023-05-01"])
+    );
+
+    for unsupported in [
+        "RRULE:FREQ=MONTHLY;COUNT=3",
+        "RRULE:FREQ=WEEKLY",
+        "RRULE:FREQ=WEEKLY;BYDAY=1MO;COUNT=3",
+        "RRULE:FREQ=DAILY;COUNT=3\nEXDATE:20230427T100000",
+    ] {
+        assert!(
+            matches!(
+                recurrence(unsupported),
+                Err(Error::UnsupportedRecurrence { .. })
+            ),
+            "{unsupported}"
+        );
+    }
+}
@@ -1,23 +1,148 @@
 //! Will call eventually call Latexmk on a previous pass input
 
-use glob::{glob, PatternError};
 use rand::Fill;
 use std::{
-    fs,
+    fmt, fs,
+    hash::{Hash, Hasher},
     path::{Path, PathBuf},
     process::Command,
+    str::FromStr,
 };
 
-use crate::passes::CompilingPass;
+use crate::{
+    event::Event,
+    passes::{cache, CompilingPass},
+};
 
 /// Options for Latexmk call pass
+#[derive(Default, Clone)]
 pub struct Options {
-    /// Path of the input file
+    /// Name of the `.tex` file written in the build directory. If not set, `seri.tex` will be used.
     pub input_path: Option<String>,
-    /// Path of the output file
+    /// Path where the PDF is copied, if set. When the pass is cached, the copy is made by
+    /// the compiler instead, so that it also happens on cache hits.
     pub output_path: Option<String>,
-    /// Save temporary files
-    pub save_temps: bool,
+    /// Directory where the document is built, which is kept afterwards. If not set, a new
+    /// temporary directory is used, and removed once the PDF is read.
+    pub build_directory: Option<PathBuf>,
+    /// Directory searched for the files included by the document, such as images. It is usually
+    /// the directory of the timetable, so that paths relative to it work.
+    pub source_directory: Option<PathBuf>,
+    /// `LaTeX` engine used to compile the document
+    pub engine: Engine,
+    /// Extra arguments given before the input file to the command run: `latexmk` for the `pdfLaTeX`,
+    /// `XeLaTeX` and `LuaLaTeX` engines, and `tectonic` for Tectonic
+    pub extra_args: Vec<String>,
+    /// Maximum number of compilation passes. If not set, the engine decides.
+    pub passes: Option<u32>,
+}
+
+/// Only the options changing the PDF are part of the cache key
+impl Hash for Options {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.input_path.hash(state);
+        self.source_directory.hash(state);
+        self.engine.hash(state);
+        self.extra_args.hash(state);
+        self.passes.hash(state);
+    }
+}
+
+/// `LaTeX` engine used to compile a document
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Engine {
+    /// `pdfLaTeX`, through Latexmk
+    PdfLatex,
+    /// `XeLaTeX`, through Latexmk
+    XeLatex,
+    /// `LuaLaTeX`, through Latexmk
+    #[default]
+    LuaLatex,
+    /// Tectonic, called directly instead of Latexmk
+    Tectonic,
+}
+
+/// The engine name given by the user is not known.
+#[derive(Debug, Error)]
+#[error("`{0}` is not a known LaTeX engine, expected pdflatex, xelatex, lualatex or tectonic")]
+pub struct UnknownEngine(pub String);
+
+impl FromStr for Engine {
+    type Err = UnknownEngine;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "pdflatex" => Ok(Self::PdfLatex),
+            "xelatex" => Ok(Self::XeLatex),
+            "lualatex" => Ok(Self::LuaLatex),
+            "tectonic" => Ok(Self::Tectonic),
+            _ => Err(UnknownEngine(input.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::PdfLatex => "pdflatex",
+            Self::XeLatex => "xelatex",
+            Self::LuaLatex => "lualatex",
+            Self::Tectonic => "tectonic",
+        })
+    }
+}
+
+impl Engine {
+    /// Build the command compiling `input` to PDF with this engine, looking for the files it
+    /// includes in `source` too
+    fn command(
+        self,
+        input: &Path,
+        extra_args: &[String],
+        passes: Option<u32>,
+        source: Option<&Path>,
+    ) -> Result<Command, std::env::JoinPathsError> {
+        let mut command = if self == Self::Tectonic {
+            let mut command = Command::new("tectonic");
+            // The log is needed to report errors
+            command.arg("--keep-logs");
+            if let Some(passes) = passes {
+                command.arg("--reruns").arg(passes.to_string());
+            }
+            if let Some(source) = source {
+                command
+                    .arg("-Z")
+                    .arg(format!("search-path={}", source.display()));
+            }
+            command
+        } else {
+            let mut command = Command::new("latexmk");
+            command.arg(match self {
+                Self::PdfLatex => "-pdf",
+                Self::XeLatex => "-pdfxe",
+                _ => "-pdflua",
+            });
+            // Do not wait for user input on errors
+            command.arg("-interaction=nonstopmode");
+            // The files read are listed in the `.fls` file, for the cache
+            command.arg("-recorder");
+            if let Some(passes) = passes {
+                command.arg("-e").arg(format!("$max_repeat={passes}"));
+            }
+            if let Some(source) = source {
+                // The empty entry at the end keeps the default search path
+                let mut paths = vec![source.to_owned()];
+                if let Some(existing) = std::env::var_os("TEXINPUTS") {
+                    paths.extend(std::env::split_paths(&existing));
+                }
+                paths.push(PathBuf::new());
+                command.env("TEXINPUTS", std::env::join_paths(paths)?);
+            }
+            command
+        };
+        command.args(extra_args).arg(input);
+        Ok(command)
+    }
 }
 
 use thiserror::Error;
@@ -31,12 +156,140 @@ pub enum Error {
     /// Error on temporary file creation
     #[error(transparent)]
     CouldNotCreateTempFile(#[from] TempFileCreationError),
-    /// Error while cleaning up
-    #[error(transparent)]
-    CouldNotCleanup(#[from] CleanupError),
+    /// Error while removing the build directory
+    #[error("Error while trying to remove the build directory: {0}")]
+    CouldNotCleanup(#[source] std::io::Error),
+    /// The source directory cannot be added to `TEXINPUTS`
+    #[error("the source directory cannot be added to TEXINPUTS: {0}")]
+    InvalidSourceDirectory(#[from] std::env::JoinPathsError),
+    /// The engine failed to compile the document
+    #[error("LaTeX compilation failed: {0}")]
+    CompilationFailed(Box<Failure>),
+}
+
+/// Marker written by the `LaTeX` backends before the code of each event, so that errors can be
+/// traced back to the event
+const EVENT_MARKER: &str = "% seri:event ";
+
+/// Write the comment marking the beginning of the code generated for an event
+#[must_use]
+pub fn event_marker(e: &Event) -> String {
+    format!(
+        "{EVENT_MARKER}{} {}",
+        e.start_date.format("%Y-%m-%d %H:%M"),
+        e.title.replace(['\n', '\r'], " ")
+    )
+}
+
+/// A `LaTeX` compilation failure, as read from the log
+#[derive(Debug, PartialEq, Eq)]
+pub struct Failure {
+    /// The first error message, without its leading `!`
+    pub message: String,
+    /// The line where the error occurred
+    pub line: Option<usize>,
+    /// The file included by the generated document where the error occurred, if not the
+    /// document itself
+    pub file: Option<String>,
+    /// The event whose code contains that line, as its date and title
+    pub event: Option<String>,
+    /// The lines of the log describing the error
+    pub excerpt: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        match (self.line, &self.file, &self.event) {
+            (Some(line), Some(file), _) => write!(f, " (line {line} of {file})")?,
+            (Some(line), None, Some(event)) => write!(f, " (line {line}, in event {event})")?,
+            (Some(line), None, None) => write!(f, " (line {line})")?,
+            _ => (),
+        }
+        if !self.excerpt.is_empty() {
+            write!(f, "\n{}", self.excerpt)?;
+        }
+        Ok(())
+    }
+}
+
+/// Maximum number of log lines kept after the error message
+const EXCERPT_LENGTH: usize = 8;
+
+/// Follow the files opened and closed in a `LaTeX` log line, shown as `(<path>` and `)`
+///
+/// Parentheses not opening a file are kept as `None` so that they close the right file.
+fn track_files<'a>(line: &'a str, files: &mut Vec<Option<&'a str>>) {
+    for (i, c) in line.char_indices() {
+        match c {
+            '(' => {
+                let name = line[i + 1..]
+                    .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                    .next()
+                    .unwrap_or_default();
+                let is_file = Path::new(name).extension().is_some_and(|e| !e.is_empty());
+                files.push(is_file.then_some(name));
+            }
+            ')' => {
+                files.pop();
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Find the first error of a `LaTeX` log, and the event of the document `latex` it comes from
+///
+/// The document is the file `input`: errors in the files it includes are not mapped to events.
+fn first_error(log: &str, latex: &str, input: &Path) -> Option<Failure> {
+    let mut files = Vec::new();
+    let mut lines = log.lines().skip_while(|l| {
+        let is_error = l.starts_with("! ");
+        if !is_error {
+            track_files(l, &mut files);
+        }
+        !is_error
+    });
+    let message = lines.next()?.trim_start_matches("! ").trim().to_owned();
+
+    let mut excerpt = vec![format!("! {message}")];
+    let mut line = None;
+    for l in lines.take(EXCERPT_LENGTH) {
+        excerpt.push(l.to_owned());
+        // TeX shows the context of the error as `l.<line> <code>`
+        if let Some(number) = l.strip_prefix("l.").and_then(|l| l.split(' ').next()) {
+            line = number.parse().ok();
+            break;
+        }
+    }
+
+    // The log may not show the document itself, for instance with Tectonic
+    let file = files
+        .into_iter()
+        .rev()
+        .flatten()
+        .next()
+        .filter(|f| Path::new(f).file_name() != input.file_name())
+        .map(str::to_owned);
+    let event = line.filter(|_| file.is_none()).and_then(|line| {
+        latex
+            .lines()
+            .take(line)
+            .filter_map(|l| l.trim().strip_prefix(EVENT_MARKER))
+            .last()
+            .map(str::to_owned)
+    });
+
+    Some(Failure {
+        message,
+        line,
+        file,
+        event,
+        excerpt: excerpt.join("\n"),
+    })
 }
 
-/// Will call Latexmk with the `$pdflatex` target, if found on the system
+/// Will call Latexmk (or Tectonic) with the chosen engine, if found on the system
 pub struct Pass {}
 
 /// Error occurring when creating a temporary file
@@ -45,6 +298,9 @@ pub enum TempFileCreationError {
     /// Error returned from random generator
     #[error("Error while trying to generate a random string: {0}")]
     CouldNotCreateRandomString(#[from] rand::Error),
+    /// Error returned when creating the directory
+    #[error("Error while trying to create the build directory: {0}")]
+    CouldNotCreateDirectory(#[from] std::io::Error),
 }
 
 /// Generate a random String of size `len` that should be valid as a file name
@@ -70,84 +326,144 @@ fn random_filename(len: usize) -> Result<String, TempFileCreationError> {
         .collect())
 }
 
-fn tex_pathbuf_from_random_string(len: usize) -> Result<PathBuf, TempFileCreationError> {
-    Ok(PathBuf::from(random_filename(len)? + ".tex"))
-}
-
-/// Will try to get a random file name that does not exist
+/// Create a new directory, only readable by the current user, in the system temporary directory
 ///
 /// # Errors
 ///
 /// Errors can happen in RNG or on IO operations.
-pub fn random_valid_filename(len: usize) -> Result<PathBuf, TempFileCreationError> {
-    let mut filepath = tex_pathbuf_from_random_string(len)?;
+pub fn create_build_directory() -> Result<PathBuf, TempFileCreationError> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
 
-    while filepath.exists() {
-        filepath = tex_pathbuf_from_random_string(len)?;
+    loop {
+        let path = std::env::temp_dir().join(format!("seri-{}", random_filename(16)?));
+        match builder.create(&path) {
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => (),
+            result => return result.map(|()| path).map_err(TempFileCreationError::from),
+        }
     }
+}
 
-    Ok(filepath)
+/// A compiled document, with the files it read from the source directory
+pub struct Compiled {
+    /// The PDF document
+    pub pdf: Vec<u8>,
+    /// The files read from the source directory, if they are known: Tectonic does not list them
+    pub inputs: Option<Vec<PathBuf>>,
 }
 
-/// Error occurring when cleaning up temporary files
-#[derive(Debug, Error)]
-pub enum CleanupError {
-    /// Error returned when removing a file
-    #[error("Error while trying to remove file: {0}")]
-    CouldNotRemoveFile(#[from] std::io::Error),
-    /// Error returned while getting the path of files to cleanup
-    #[error("Error while converting a blob expression to &str")]
-    CouldNotGetPathToString,
-    /// Error returned from glob to get temporary files list
-    #[error("Error while trying to get the list of files to cleanup: {0}")]
-    CouldNotGetFileList(#[from] PatternError),
-}
-
-fn cleanup(input_path: &Path) -> Result<(), CleanupError> {
-    for entry in glob(
-        input_path
-            .with_extension("*")
-            .to_str()
-            .map_or_else(|| Err(CleanupError::CouldNotGetPathToString), Ok)?,
-    )? {
-        let e = entry.unwrap();
-        if e.is_file() {
-            fs::remove_file(e)?;
-        }
+impl cache::Recorded for Compiled {
+    type Stored = Vec<u8>;
+
+    fn into_parts(self) -> (Self::Stored, Option<Vec<PathBuf>>) {
+        (self.pdf, self.inputs)
     }
-    Ok(())
 }
 
-impl CompilingPass<&str, Options> for Pass {
-    type Residual = Vec<u8>;
-    type Error = Error;
-    fn apply_with(latex: &str, options: Options) -> Result<Self::Residual, Self::Error> {
-        let input_file = options
-            .input_path
-            .map_or_else(|| random_valid_filename(16), |s| Ok(PathBuf::from(s)));
+/// The files of `source` read by the engine, from the `INPUT` lines of its `.fls` recorder file
+fn recorded_inputs(fls: &str, source: &Path) -> Vec<PathBuf> {
+    let mut inputs: Vec<PathBuf> = fls
+        .lines()
+        .filter_map(|l| l.strip_prefix("INPUT "))
+        .map(PathBuf::from)
+        .filter(|path| path.starts_with(source))
+        .collect();
+    inputs.sort();
+    inputs.dedup();
+    inputs
+}
 
-        let input_unwrapped = input_file?;
+/// Compile `latex` in the build directory `directory`, and read the resulting PDF
+fn build(latex: &str, directory: &Path, options: &Options) -> Result<Compiled, Error> {
+    let input = PathBuf::from(options.input_path.as_deref().unwrap_or("seri.tex"));
+    let input = PathBuf::from(input.file_name().unwrap_or_else(|| "seri.tex".as_ref()));
+    fs::write(directory.join(&input), latex)?;
+    // The engine runs in the build directory, so relative paths would be wrong there
+    let source = options
+        .source_directory
+        .as_deref()
+        .map(std::path::absolute)
+        .transpose()?;
 
-        fs::write(&input_unwrapped, latex)?;
+    let status = options
+        .engine
+        .command(
+            &input,
+            &options.extra_args,
+            options.passes,
+            source.as_deref(),
+        )?
+        .current_dir(directory)
+        // TODO Will need a way to output that cleanly
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .status()?;
 
-        let mut latexmk = Command::new("latexmk")
-            .arg("-pdflua")
-            .arg(&input_unwrapped)
-            // TODO Will need a way to output that cleanly
-            .stdout(std::process::Stdio::null())
-            .spawn()?;
+    if !status.success() {
+        let log =
+            fs::read_to_string(directory.join(input.with_extension("log"))).unwrap_or_default();
+        return Err(Error::CompilationFailed(Box::new(
+            first_error(&log, latex, &input).unwrap_or_else(|| Failure {
+                message: format!("the engine exited with {status}"),
+                line: None,
+                file: None,
+                event: None,
+                excerpt: String::new(),
+            }),
+        )));
+    }
 
-        latexmk.stdout.take();
+    let pdf = directory.join(input.with_extension("pdf"));
+    if let Some(output) = &options.output_path {
+        fs::copy(&pdf, output)?;
+    }
+    let inputs = match (&source, options.engine) {
+        (None, _) => Some(Vec::new()),
+        (Some(_), Engine::Tectonic) => None,
+        (Some(source), _) => fs::read_to_string(directory.join(input.with_extension("fls")))
+            .ok()
+            .map(|fls| recorded_inputs(&fls, source)),
+    };
+    Ok(Compiled {
+        pdf: fs::read(pdf)?,
+        inputs,
+    })
+}
 
-        latexmk.wait_with_output()?;
+/// Like [`Pass`], also giving the files read from the source directory, so that the result can
+/// be cached with [`cache::CachedRecorded`]
+pub struct Recording {}
 
-        let ret = fs::read(input_unwrapped.with_extension("pdf")).map_err(Error::from);
+impl CompilingPass<&str, Options> for Recording {
+    type Residual = Compiled;
+    type Error = Error;
 
-        if !options.save_temps {
-            cleanup(&input_unwrapped)?;
+    fn apply(latex: &str) -> Result<Self::Residual, Self::Error> {
+        Self::apply_with(latex, Options::default())
+    }
+
+    fn apply_with(latex: &str, options: Options) -> Result<Self::Residual, Self::Error> {
+        if let Some(directory) = &options.build_directory {
+            fs::create_dir_all(directory)?;
+            return build(latex, directory, &options);
         }
 
-        ret
+        let directory = create_build_directory()?;
+        let compiled = build(latex, &directory, &options);
+        let cleanup = fs::remove_dir_all(&directory);
+        // A failed cleanup is only reported when the build succeeded
+        let compiled = compiled?;
+        cleanup.map_err(Error::CouldNotCleanup)?;
+        Ok(compiled)
+    }
+}
+
+impl CompilingPass<&str, Options> for Pass {
+    type Residual = Vec<u8>;
+    type Error = Error;
+    fn apply_with(latex: &str, options: Options) -> Result<Self::Residual, Self::Error> {
+        Recording::apply_with(latex, options).map(|compiled| compiled.pdf)
     }
 
     fn apply(latex: &str) -> Result<Self::Residual, Self::Error> {
@@ -156,8 +472,73 @@ impl CompilingPass<&str, Options> for Pass {
             Options {
                 input_path: None,
                 output_path: None,
-                save_temps: false,
+                build_directory: None,
+                source_directory: None,
+                engine: Engine::default(),
+                extra_args: Vec::new(),
+                passes: None,
             },
         )
     }
 }
+
+#[test]
+fn test_first_error() {
+    let latex = "\\begin{document}\n% seri:event 2023-04-27 10:10 Hello\n\\node{a};\n\\foo\n\\end{document}";
+    let log = "This is LuaHBTeX\n(./seri.tex (/texmf/article.cls (preloaded format)\n)\n! Undefined control sequence.\nl.4 \\foo\n\nHere is how much of TeX's memory you used:";
+    let input = Path::new("seri.tex");
+
+    assert_eq!(
+        first_error(log, latex, input),
+        Some(Failure {
+            message: "Undefined control sequence.".to_owned(),
+            line: Some(4),
+            file: None,
+            event: Some("2023-04-27 10:10 Hello".to_owned()),
+            excerpt: "! Undefined control sequence.\nl.4 \\foo".to_owned(),
+        })
+    );
+    // An error in an included file is not in any event
+    let log = "This is LuaHBTeX\n(./seri.tex (./talks/hello.tex\n! Undefined control sequence.\nl.4 \\foo";
+    assert_eq!(
+        first_error(log, latex, input),
+        Some(Failure {
+            message: "Undefined control sequence.".to_owned(),
+            line: Some(4),
+            file: Some("./talks/hello.tex".to_owned()),
+            event: None,
+            excerpt: "! Undefined control sequence.\nl.4 \\foo".to_owned(),
+        })
+    );
+    assert_eq!(first_error("No error here", latex, input), None);
+}
+
+#[test]
+fn test_command() {
+    let source = Path::new("/talks");
+    let latexmk = Engine::LuaLatex
+        .command(Path::new("seri.tex"), &[], None, Some(source))
+        .unwrap();
+    let texinputs = latexmk
+        .get_envs()
+        .find(|(key, _)| *key == "TEXINPUTS")
+        .and_then(|(_, value)| value)
+        .unwrap();
+    let paths: Vec<PathBuf> = std::env::split_paths(texinputs).collect();
+    assert_eq!(paths.first().map(PathBuf::as_path), Some(source));
+    assert_eq!(paths.last(), Some(&PathBuf::new()));
+
+    let tectonic = Engine::Tectonic
+        .command(Path::new("seri.tex"), &[], None, Some(source))
+        .unwrap();
+    assert!(tectonic.get_args().any(|a| a == "search-path=/talks"));
+}
+
+#[test]
+fn test_recorded_inputs() {
+    let fls = "PWD /tmp/seri-a\nINPUT /usr/share/texmf/article.cls\nINPUT /talks/logo.png\nINPUT ./seri.tex\nINPUT /talks/logo.png\nOUTPUT seri.pdf";
+    assert_eq!(
+        recorded_inputs(fls, Path::new("/talks")),
+        vec![PathBuf::from("/talks/logo.png")]
+    );
+}
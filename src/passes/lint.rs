@@ -0,0 +1,107 @@
+//! Analysis pass, reporting likely mistakes in a timetable
+
+use std::fmt;
+
+use chrono::Duration;
+
+use crate::{
+    event::{Event, Type},
+    locale::Locale,
+    passes::CompilingPass,
+};
+
+/// Checks the events for overlaps in a room, talks without speakers and events without duration
+pub struct Pass {}
+
+/// A likely mistake in a timetable
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Warning {
+    /// Position of the event concerned in the checked events
+    pub index: usize,
+    /// The event concerned, as its date and title
+    pub event: String,
+    /// What is wrong with the event
+    pub message: String,
+}
+
+impl Warning {
+    fn new(index: usize, e: &Event, message: String) -> Self {
+        Self {
+            index,
+            event: format!(
+                "{} {}",
+                e.start_date.format("%Y-%m-%d %H:%M"),
+                e.display_title(Locale::default())
+            ),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.event, self.message)
+    }
+}
+
+impl CompilingPass<&[Event]> for Pass {
+    type Residual = Vec<Warning>;
+    type Error = std::convert::Infallible;
+
+    fn apply(events: &[Event]) -> Result<Self::Residual, Self::Error> {
+        let mut warnings = Vec::new();
+        let mut sorted: Vec<(usize, &Event)> = events.iter().enumerate().collect();
+        sorted.sort_by_key(|(_, e)| e.start_date);
+
+        for (i, &(index, e)) in sorted.iter().enumerate() {
+            if e.event_type == Type::Talk && e.speakers.is_empty() {
+                warnings.push(Warning::new(index, e, "talk without speakers".to_owned()));
+            }
+            if e.duration == 0 {
+                warnings.push(Warning::new(index, e, "event without duration".to_owned()));
+            }
+
+            // Events without a room are all in the same room
+            let end = e.start_date + Duration::minutes(i64::from(e.duration));
+            let room = e.field("room");
+            for &(other_index, other) in sorted[i + 1..]
+                .iter()
+                .take_while(|(_, other)| other.start_date < end)
+                .filter(|(_, other)| other.field("room") == room)
+            {
+                warnings.push(Warning::new(
+                    other_index,
+                    other,
+                    format!("overlaps with `{}`", e.display_title(Locale::default())),
+                ));
+            }
+        }
+        Ok(warnings)
+    }
+}
+
+#[test]
+fn test_lint() {
+    use std::str::FromStr;
+
+    let events: Vec<Event> = [
+        "title: A\ndate: 2023-04-27 10:00\nduration: 60\nspeakers: alice",
+        "title: B\ndate: 2023-04-27 10:30\nduration: 30\nspeakers: bob",
+        "title: C\ndate: 2023-04-27 10:30\nduration: 30\nspeakers: carol\nroom: Annex",
+        "title: D\ndate: 2023-04-27 11:00\nduration: 30",
+    ]
+    .iter()
+    .map(|e| Event::from_str(e).unwrap())
+    .collect();
+
+    let Ok(warnings) = Pass::apply(&events);
+    assert_eq!(warnings[0].index, 1);
+    let messages: Vec<String> = warnings.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "2023-04-27 10:30 B: overlaps with `A`",
+            "2023-04-27 11:00 D: talk without speakers",
+        ]
+    );
+}
@@ -1,39 +1,30 @@
 //! Parsing compilation passes
 
-use crate::{event::Event, passes::CompilingPass};
-
-use std::str::FromStr;
+use crate::{
+    event::{Event, ParsingError},
+    passes::CompilingPass,
+    syntax,
+};
 
 /// Parses a string slice into an Event vector.
-/// For now, the grammar is defined as follow in EBNF
-/// ```ebnf
-/// line return = "\n" ;
-///
-/// new paragraph = line return , line return ;
-///
-/// S = { " " | "\n" | "\t" } ;
-///
-/// delimiter = "---" , line return ;
-///
-/// key = litteral ;
-/// value = litteral ;
-/// pair = S , key , S , ":" , value , S , line return ;
-///
-/// event header = { pair } ;
 ///
-/// event description = (TODO) ;
+/// The grammar of the Seri language is specified in `spec/seri.md`.
 ///
-/// event = event header , new paragraph , event description ;
+/// Comments may appear on any line, and are not part of the events. A block comment spanning a
+/// whole event disables it.
 ///
-/// timetable = event , ( delimiter , event ) * ;
-/// ```
+/// Values keep their escapes, that are removed once the value is interpreted: a backslash
+/// escapes the following character, which allows commas and brackets in lists.
 pub struct ParseTimetable {}
 
 impl CompilingPass<&str> for ParseTimetable {
     type Residual = Vec<Event>;
-    type Error = <Event as FromStr>::Err;
+    type Error = ParsingError;
 
     fn apply(s: &str) -> Result<Self::Residual, Self::Error> {
-        s.split("---").map(Event::from_str).collect()
+        syntax::group(&syntax::tokenize(s)?)
+            .iter()
+            .map(Event::try_from)
+            .collect()
     }
 }
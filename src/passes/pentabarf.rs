@@ -0,0 +1,281 @@
+//! Pentabarf/Frab XML schedule backend
+//!
+//! The `schedule.xml` format is read by conference companion apps such as `Giggity` or `ConfClerk`.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use chrono::{Duration, NaiveDate};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    event::{find_bounding_box, Event, InvalidDatetime},
+    language,
+    passes::{html::escape, CompilingPass},
+};
+
+/// Backend outputing events to a Pentabarf/Frab `schedule.xml` document
+pub struct Pass {}
+
+/// Options for the Pentabarf backend
+#[derive(Default, Clone)]
+pub struct Options {
+    /// Title of the conference. If not set, `Seminar` will be used.
+    pub title: Option<String>,
+    /// Room of the events without a `room` setting. If not set, `Main` will be used.
+    pub default_room: Option<String>,
+}
+
+/// Error occuring when compiling an event list to a Pentabarf schedule.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The list of events was empty.
+    #[error("no event was provided")]
+    NoEventProvided,
+    /// The datetime of either the first day or last day of the bounding box is not valid.
+    #[error(transparent)]
+    InvalidDatetime(#[from] InvalidDatetime),
+}
+
+/// Events of a day with their identifier, by room
+type Rooms<'a> = BTreeMap<String, Vec<(usize, &'a Event)>>;
+
+/// Format a duration in minutes as `HH:MM`
+fn hours_minutes(minutes: u32) -> String {
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// Build a UUID from the SHA-256 of the title, start and room of the event, as apps use it to
+/// keep favourites. It is the same across runs and versions of Seri.
+fn guid(e: &Event, room: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [e.title.as_str(), &e.start_date.to_rfc3339(), room] {
+        // The length keeps the parts apart
+        hasher.update(part.len().to_le_bytes());
+        hasher.update(part);
+    }
+    let mut bytes = hasher.finalize();
+    // Version 8 (custom) and RFC 9562 variant
+    bytes[6] = (bytes[6] & 0x0f) | 0x80;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    bytes
+        .iter()
+        .take(16)
+        .enumerate()
+        .fold(String::new(), |mut r, (i, byte)| {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                r.push('-');
+            }
+            let _ = write!(r, "{byte:02x}");
+            r
+        })
+}
+
+/// Turn a title into an identifier usable in URLs
+fn slug(title: &str) -> String {
+    title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Generate an `<event>` element
+fn event_xml(e: &Event, id: usize, room: &str, persons: &[&String]) -> String {
+    let optional = |tag: &str, value: Option<String>| {
+        value.map_or_else(
+            || format!("<{tag}/>"),
+            |v| format!("<{tag}>{}</{tag}>", escape(&v)),
+        )
+    };
+
+    let mut r = format!(
+        r#"
+      <event guid="{}" id="{id}">"#,
+        guid(e, room)
+    );
+    let _ = write!(
+        r,
+        r"
+        <date>{}</date>
+        <start>{}</start>
+        <duration>{}</duration>
+        <room>{}</room>
+        <slug>{}</slug>
+        <title>{}</title>
+        {}
+        {}
+        <type>{}</type>
+        {}
+        {}
+        <description/>
+        <persons>",
+        e.start_date.to_rfc3339(),
+        e.start_date.format("%H:%M"),
+        hours_minutes(e.duration),
+        escape(room),
+        slug(&e.title),
+        escape(&e.title),
+        optional("subtitle", e.field("subtitle")),
+        optional("track", e.field("track")),
+        e.event_type,
+        optional(
+            "language",
+            e.languages.first().map(|l| language::code(*l).to_owned())
+        ),
+        optional("abstract", e.description.clone()),
+    );
+    for speaker in &e.speakers {
+        let person_id = persons.iter().position(|p| *p == speaker).unwrap_or(0) + 1;
+        let _ = write!(
+            r,
+            r#"
+          <person id="{person_id}">{}</person>"#,
+            escape(speaker)
+        );
+    }
+    r += r"
+        </persons>
+        <links>";
+    for (key, url) in e.extra.iter().filter(|(_, v)| v.starts_with("http")) {
+        let _ = write!(
+            r,
+            r#"
+          <link href="{}">{}</link>"#,
+            escape(url),
+            escape(key)
+        );
+    }
+    r += r"
+        </links>
+      </event>";
+    r
+}
+
+impl CompilingPass<Vec<Event>, Options> for Pass {
+    type Residual = String;
+    type Error = Error;
+
+    fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
+        Self::apply_with(
+            events,
+            Options {
+                title: None,
+                default_room: None,
+            },
+        )
+    }
+
+    fn apply_with(mut events: Vec<Event>, options: Options) -> Result<Self::Residual, Self::Error> {
+        events.sort_by_key(|e| e.start_date);
+        let title = options.title.unwrap_or_else(|| "Seminar".to_owned());
+        let default_room = options.default_room.unwrap_or_else(|| "Main".to_owned());
+
+        let bb = find_bounding_box(&events).ok_or(Error::NoEventProvided)?;
+        let first_day = bb.first_day()?.date_naive();
+        let last_day = bb.last_day()?.date_naive();
+
+        let mut persons: Vec<&String> = events.iter().flat_map(|e| &e.speakers).collect();
+        persons.sort();
+        persons.dedup();
+
+        // Events are grouped by day, then by room
+        let mut days: BTreeMap<NaiveDate, Rooms> = BTreeMap::new();
+        for (id, e) in events.iter().enumerate() {
+            let room = e.field("room").unwrap_or_else(|| default_room.clone());
+            days.entry(e.start_date.date_naive())
+                .or_default()
+                .entry(room)
+                .or_default()
+                .push((id + 1, e));
+        }
+
+        let mut r = r#"<?xml version="1.0" encoding="UTF-8"?>
+<schedule>
+  <version>1.0</version>
+  <conference>"#
+            .to_owned();
+        let _ = write!(
+            r,
+            r"
+    <title>{}</title>
+    <start>{first_day}</start>
+    <end>{last_day}</end>
+    <days>{}</days>
+    <timeslot_duration>00:05</timeslot_duration>
+  </conference>",
+            escape(&title),
+            (last_day - first_day).num_days() + 1,
+        );
+
+        for (day, rooms) in &days {
+            let day_events = rooms.values().flatten().map(|(_, e)| e);
+            let start = day_events.clone().map(|e| e.start_date).min();
+            let end = day_events
+                .map(|e| e.start_date + Duration::minutes(i64::from(e.duration)))
+                .max();
+            let _ = write!(
+                r,
+                r#"
+  <day index="{}" date="{day}" start="{}" end="{}">"#,
+                // Days are numbered across the whole span, as counted in `<days>`
+                (*day - first_day).num_days() + 1,
+                start.map(|d| d.to_rfc3339()).unwrap_or_default(),
+                end.map(|d| d.to_rfc3339()).unwrap_or_default(),
+            );
+            for (room, room_events) in rooms {
+                let _ = write!(
+                    r,
+                    r#"
+    <room name="{}">"#,
+                    escape(room)
+                );
+                for (id, e) in room_events {
+                    r += &event_xml(e, *id, room, &persons);
+                }
+                r += r"
+    </room>";
+            }
+            r += r"
+  </day>";
+        }
+        r += "\n</schedule>\n";
+
+        Ok(r)
+    }
+}
+
+#[test]
+fn test_pentabarf() {
+    let events = crate::passes::parser::ParseTimetable::apply(
+        "title: Opening\ndate: 2023-04-27 09:00\nduration: 30\nroom: Hall\n---\ntitle: Closing & drinks\ndate: 2023-04-29 17:00\nduration: 90\nspeakers: Ada",
+    )
+    .unwrap();
+    let xml = Pass::apply(events).unwrap();
+
+    // The second day has no event, and the last one is still the third
+    assert!(
+        xml.contains("<start>2023-04-27</start>\n    <end>2023-04-29</end>\n    <days>3</days>")
+    );
+    assert!(xml.contains(r#"<day index="1" date="2023-04-27""#));
+    assert!(xml.contains(r#"<day index="3" date="2023-04-29""#));
+    assert!(!xml.contains(r#"index="2""#));
+    assert!(xml.contains(r#"<room name="Hall">"#) && xml.contains(r#"<room name="Main">"#));
+    assert!(xml.contains("<title>Closing &amp; drinks</title>"));
+    assert!(xml.contains("<duration>01:30</duration>"));
+    assert!(xml.contains(r#"<person id="1">Ada</person>"#));
+
+    // The identifiers are UUIDs, which only depend on the title, start and room
+    let guids: Vec<&str> = xml.split(r#"guid=""#).skip(1).map(|s| &s[..36]).collect();
+    assert_eq!(guids.len(), 2);
+    assert_ne!(guids[0], guids[1]);
+    assert!(guids
+        .iter()
+        .all(|g| g.split('-').map(str::len).eq([8, 4, 4, 4, 12]) && g[14..].starts_with('8')));
+    let again = crate::passes::parser::ParseTimetable::apply(
+        "title: Opening\ndate: 2023-04-27 09:00\nduration: 45\nroom: Hall\nspeakers: Bob",
+    )
+    .unwrap();
+    assert!(Pass::apply(again).unwrap().contains(guids[0]));
+}
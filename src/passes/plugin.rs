@@ -0,0 +1,227 @@
+//! Passes running an external program, to extend Seri in any language
+//!
+//! The program is given the events as a JSON array on its standard input. A [`Transform`] plugin
+//! writes the transformed events back as a JSON array on its standard output, while a [`Render`]
+//! plugin writes a document. Anything the plugin writes to its standard error is returned along
+//! with its output, or in its error if it fails.
+
+use std::{
+    io::{self, Write},
+    process::{Command, ExitStatus, Stdio},
+    str::FromStr,
+    thread,
+};
+
+use crate::{event::Event, passes::CompilingPass};
+
+/// Transforms the events with an external program, reading the events it outputs
+pub struct Transform {}
+
+/// Writes the events to a document with an external program
+pub struct Render {}
+
+/// Options for the plugin passes
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// The program to run, found in `PATH` if it is not a path
+    pub program: String,
+    /// Arguments given to the program
+    pub args: Vec<String>,
+}
+
+impl FromStr for Options {
+    type Err = Error;
+
+    /// Read a command line, such as `./rooms.py --room 'Main hall'`, split as a shell would
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let words =
+            shell_words::split(input).map_err(|_| Error::UnclosedQuote(input.to_owned()))?;
+        let mut words = words.into_iter();
+        let program = words.next().ok_or(Error::MissingCommand)?;
+        Ok(Self {
+            program,
+            args: words.collect(),
+        })
+    }
+}
+
+/// Error occuring when running a plugin.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// No program was given
+    #[error("no plugin command given")]
+    MissingCommand,
+    /// The command line has a quote that is never closed
+    #[error("`{0}` has a quote that is never closed")]
+    UnclosedQuote(String),
+    /// The events could not be written as JSON
+    #[error("could not write the events as JSON: {0}")]
+    CouldNotSerialize(#[source] serde_json::Error),
+    /// The program could not be started, or given its input
+    #[error("could not run plugin `{program}`: {source}")]
+    CouldNotRun {
+        /// The program of the plugin
+        program: String,
+        /// The underlying error
+        #[source]
+        source: io::Error,
+    },
+    /// The program exited with an error
+    #[error("plugin `{program}` failed ({status}){}", stderr_excerpt(.stderr))]
+    Failed {
+        /// The program of the plugin
+        program: String,
+        /// The exit status of the program
+        status: ExitStatus,
+        /// What the program wrote to its standard error
+        stderr: String,
+    },
+    /// The program did not output a valid JSON array of events
+    #[error("plugin `{program}` did not output a JSON array of events: {source}")]
+    MalformedOutput {
+        /// The program of the plugin
+        program: String,
+        /// The underlying error
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// The result of a plugin
+#[derive(Debug)]
+pub struct Output<T> {
+    /// The transformed events, or the document
+    pub value: T,
+    /// What the plugin wrote to its standard error
+    pub stderr: String,
+}
+
+/// The last lines of the standard error of a failed plugin, to show in its error
+fn stderr_excerpt(stderr: &str) -> String {
+    let lines: Vec<&str> = stderr.trim_end().lines().collect();
+    if lines.is_empty() {
+        return String::new();
+    }
+    format!(":\n{}", lines[lines.len().saturating_sub(10)..].join("\n"))
+}
+
+/// Run the program on the events, returning its standard output
+fn run(events: &[Event], options: &Options) -> Result<Output<Vec<u8>>, Error> {
+    if options.program.is_empty() {
+        return Err(Error::MissingCommand);
+    }
+    let could_not_run = |source| Error::CouldNotRun {
+        program: options.program.clone(),
+        source,
+    };
+    let input = serde_json::to_vec(events).map_err(Error::CouldNotSerialize)?;
+
+    let mut child = Command::new(&options.program)
+        .args(&options.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(could_not_run)?;
+
+    // Written from another thread, so that a plugin writing before it has read all its input
+    // cannot block
+    let mut stdin = child.stdin.take().expect("the standard input is piped");
+    let writer = thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output().map_err(could_not_run)?;
+    let written = writer.join().expect("the writing thread does not panic");
+
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    if !output.status.success() {
+        return Err(Error::Failed {
+            program: options.program.clone(),
+            status: output.status,
+            stderr,
+        });
+    }
+    match written {
+        // The plugin does not have to read the events
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(could_not_run(e)),
+        _ => (),
+    }
+    Ok(Output {
+        value: output.stdout,
+        stderr,
+    })
+}
+
+impl CompilingPass<Vec<Event>, Options> for Transform {
+    type Residual = Output<Vec<Event>>;
+    type Error = Error;
+
+    fn apply(_: Vec<Event>) -> Result<Self::Residual, Self::Error> {
+        Err(Error::MissingCommand)
+    }
+
+    fn apply_with(events: Vec<Event>, options: Options) -> Result<Self::Residual, Self::Error> {
+        let output = run(&events, &options)?;
+        let events =
+            serde_json::from_slice(&output.value).map_err(|source| Error::MalformedOutput {
+                program: options.program,
+                source,
+            })?;
+        Ok(Output {
+            value: events,
+            stderr: output.stderr,
+        })
+    }
+}
+
+impl CompilingPass<Vec<Event>, Options> for Render {
+    type Residual = Output<Vec<u8>>;
+    type Error = Error;
+
+    fn apply(_: Vec<Event>) -> Result<Self::Residual, Self::Error> {
+        Err(Error::MissingCommand)
+    }
+
+    fn apply_with(events: Vec<Event>, options: Options) -> Result<Self::Residual, Self::Error> {
+        run(&events, &options)
+    }
+}
+
+#[test]
+fn test_plugin() {
+    let events = vec![Event::from_str(
+        "title: Welcome\ndate: 2023-04-27 10:00\nduration: 15\nlang: fr\nrepeat: daily until 2023-04-28\nroom: Annex",
+    )
+    .unwrap()];
+
+    // `cat` outputs the events unchanged
+    let round_trip = Transform::apply_with(events.clone(), "cat".parse().unwrap())
+        .unwrap()
+        .value;
+    assert_eq!(round_trip[0].title, "Welcome");
+    assert_eq!(round_trip[0].start_date, events[0].start_date);
+    assert_eq!(round_trip[0].recurrence, events[0].recurrence);
+    assert_eq!(round_trip[0].field("room").as_deref(), Some("Annex"));
+
+    let json = Render::apply_with(events.clone(), "cat".parse().unwrap()).unwrap();
+    assert!(String::from_utf8(json.value)
+        .unwrap()
+        .contains(r#""repeat":"daily until 2023-04-28""#));
+    assert!(json.stderr.is_empty());
+
+    // Arguments can contain spaces, and the standard error is returned
+    let options: Options = r#"sh -c "echo 'in the hall' >&2; echo '<p>'""#.parse().unwrap();
+    assert_eq!(options.args.len(), 2);
+    let output = Render::apply_with(events.clone(), options).unwrap();
+    assert_eq!(output.value, b"<p>\n");
+    assert_eq!(output.stderr, "in the hall\n");
+    assert!(matches!(
+        "sh -c 'echo".parse::<Options>(),
+        Err(Error::UnclosedQuote(_))
+    ));
+
+    assert!(matches!(
+        Transform::apply_with(events.clone(), "echo nope".parse().unwrap()),
+        Err(Error::MalformedOutput { .. })
+    ));
+    let failed = Render::apply_with(events, "false".parse().unwrap());
+    assert!(matches!(failed, Err(Error::Failed { .. })));
+}
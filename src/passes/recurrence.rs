@@ -0,0 +1,56 @@
+//! Expansion of recurring events
+
+use chrono::{Local, TimeZone};
+
+use crate::{
+    event::{Event, InvalidDatetime},
+    passes::CompilingPass,
+};
+
+/// Replaces every recurring event by one concrete event per occurrence
+pub struct Pass {}
+
+/// Error occurring when expanding recurring events.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// An occurrence falls on a local time that does not exist or is ambiguous.
+    #[error(transparent)]
+    InvalidDatetime(#[from] InvalidDatetime),
+}
+
+/// Expand a single event into its occurrences, keeping the time of day of the original
+fn expand(event: Event) -> Result<Vec<Event>, Error> {
+    let Some(recurrence) = &event.recurrence else {
+        return Ok(vec![event]);
+    };
+
+    let time = event.start_date.time();
+    recurrence
+        .occurrences(event.start_date.date_naive())
+        .into_iter()
+        .map(|day| {
+            let start_date = Local
+                .from_local_datetime(&day.and_time(time))
+                .single()
+                .ok_or(InvalidDatetime)?;
+            Ok(Event {
+                start_date,
+                recurrence: None,
+                ..event.clone()
+            })
+        })
+        .collect()
+}
+
+impl CompilingPass<Vec<Event>> for Pass {
+    type Residual = Vec<Event>;
+    type Error = Error;
+
+    fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
+        let mut expanded = Vec::with_capacity(events.len());
+        for e in events {
+            expanded.extend(expand(e)?);
+        }
+        Ok(expanded)
+    }
+}
@@ -0,0 +1,157 @@
+//! Seri language backend, to write timetables read from other formats
+
+use std::fmt::Write;
+
+use crate::{
+    event::Event,
+    language,
+    passes::CompilingPass,
+    syntax::{code_fence, is_code_fence_end},
+};
+
+/// Backend outputing events to a `.seri` timetable
+pub struct Pass {}
+
+/// Escape a value so that it is read back as is
+fn escape_value(value: &str) -> String {
+    let escaped = value.replace('\\', r"\\");
+    if value.contains('\n') || value.starts_with('"') || value != value.trim() {
+        format!("\"{}\"", escaped.replace('"', "\\\""))
+    } else {
+        escaped
+    }
+}
+
+/// Escape a list item so that commas and brackets are not read as separators
+fn escape_item(item: &str) -> String {
+    let mut r = String::with_capacity(item.len());
+    for c in item.chars() {
+        if matches!(c, '\\' | ',' | '[' | ']') {
+            r.push('\\');
+        }
+        r.push(c);
+    }
+    r
+}
+
+/// Escape the description lines that would be read as delimiters or comments
+///
+/// Code blocks are read literally, except for delimiters, so only those are escaped there.
+fn escape_description(description: &str) -> String {
+    let mut fence = None;
+    description
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim();
+            let escape = if let Some(open) = fence {
+                if is_code_fence_end(line, open) {
+                    fence = None;
+                }
+                trimmed == "---"
+            } else {
+                fence = code_fence(line);
+                trimmed == "---" || trimmed == "/*" || trimmed.starts_with("//")
+            };
+            if escape {
+                format!("\\{line}")
+            } else {
+                line.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Write an event in the Seri language
+fn event_to_seri(e: &Event) -> String {
+    let mut r = String::new();
+    if !e.title.is_empty() {
+        let _ = writeln!(r, "title: {}", escape_value(&e.title));
+    }
+    for (language, title) in &e.titles {
+        let _ = writeln!(
+            r,
+            "title.{}: {}",
+            language::code(*language),
+            escape_value(title)
+        );
+    }
+    let _ = writeln!(r, "date: {}", e.start_date.format("%Y-%m-%d %H:%M"));
+    let _ = writeln!(r, "duration: {}", e.duration);
+    let _ = writeln!(r, "type: {}", e.event_type);
+    if !e.speakers.is_empty() {
+        let speakers: Vec<String> = e.speakers.iter().map(|s| escape_item(s)).collect();
+        let _ = writeln!(r, "speakers: {}", speakers.join(", "));
+    }
+    if let Some(lang) = e.field("lang") {
+        let _ = writeln!(r, "lang: {lang}");
+    }
+    if !e.tags.is_empty() {
+        let tags: Vec<String> = e.tags.iter().map(|t| escape_item(t)).collect();
+        let _ = writeln!(r, "tags: {}", tags.join(", "));
+    }
+    if let Some(recurrence) = &e.recurrence {
+        let _ = writeln!(r, "repeat: {recurrence}");
+    }
+    for (key, value) in &e.extra {
+        let _ = writeln!(r, "{}: {}", key.replace(':', r"\:"), escape_value(value));
+    }
+    if let Some(description) = e.tagged_description() {
+        let _ = write!(r, "\n{}\n", escape_description(&description));
+    }
+    r
+}
+
+impl CompilingPass<Vec<Event>> for Pass {
+    type Residual = String;
+    type Error = std::convert::Infallible;
+
+    fn apply(mut events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
+        events.sort_by_key(|e| e.start_date);
+        Ok(events
+            .iter()
+            .map(event_to_seri)
+            .collect::<Vec<_>>()
+            .join("\n---\n"))
+    }
+}
+
+#[test]
+fn test_round_trip() {
+    use std::str::FromStr;
+
+    let source = "title: \"A \\\"quoted\\\"\n title\"\ndate: 2023-04-27 10:00\nduration: 30\nspeakers: Smith\\, J., Doe\nroom: A\\: 12\ntitle.fr: Un titre\n\n\\---\n\\// not a comment\n\\::: fr\n::: fr\nEn français";
+    let event = Event::from_str(source).unwrap();
+    let written = event_to_seri(&event);
+    let read = Event::from_str(&written).unwrap();
+
+    assert_eq!(read.title, event.title);
+    assert_eq!(read.speakers, vec!["Smith, J.", "Doe"]);
+    assert_eq!(read.extra, event.extra);
+    assert_eq!(read.titles, event.titles);
+    assert_eq!(
+        read.description.as_deref(),
+        Some("---\n// not a comment\n::: fr")
+    );
+    assert_eq!(read.descriptions, event.descriptions);
+    assert_eq!(read.descriptions.len(), 1);
+}
+
+#[test]
+fn test_round_trip_code_block() {
+    use std::str::FromStr;
+
+    let source =
+        "title: Code\ndate: 2023-04-27 10:00\nduration: 30\n\n```c\n// a comment\n/*\n\\---\n```\n\\// after";
+    let event = Event::from_str(source).unwrap();
+    assert_eq!(
+        event.description.as_deref(),
+        Some("```c\n// a comment\n/*\n---\n```\n// after")
+    );
+
+    // Only the delimiter is escaped inside the code block
+    let written = event_to_seri(&event);
+    assert!(written.ends_with("\n```c\n// a comment\n/*\n\\---\n```\n\\// after\n"));
+    let read = Event::from_str(&written).unwrap();
+    assert_eq!(read.description, event.description);
+}
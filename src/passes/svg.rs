@@ -0,0 +1,260 @@
+//! SVG backend, drawing the same timetable as the `TikZ` backend without needing `LaTeX`
+
+use std::{fmt::Write, str::FromStr};
+
+use chrono::{Duration, NaiveDate, Timelike};
+
+use crate::{
+    event::{end_hour, find_bounding_box, Event, InvalidDatetime},
+    locale::Locale,
+    passes::{html::escape, CompilingPass},
+    templating,
+};
+
+/// Backend outputing events to a standalone SVG image containing a timetable
+pub struct Pass {}
+
+/// Options for the SVG backend
+#[derive(Default, Clone)]
+pub struct Options {
+    /// Path to the template file. If not set, the default template (`data/template.svg`) will be used.
+    pub template_path: Option<String>,
+    /// Language of the dates and of the words joining speakers
+    pub locale: Locale,
+}
+
+/// Error occuring when compiling an event list to SVG.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The event could not be parsed.
+    #[error(transparent)]
+    CouldNotParseEvent(#[from] <Event as FromStr>::Err),
+    /// The list of events was empty.
+    #[error("no event was provided")]
+    NoEventProvided,
+    /// The datetime of either the first day or last day of the bounding box is not valid.
+    #[error(transparent)]
+    InvalidDatetime(#[from] InvalidDatetime),
+    /// An error occurred while trying to read the template file
+    #[error("Error while trying to read the template file: {0}")]
+    CouldNotReadTemplate(#[from] std::io::Error),
+    /// An error occurred while trying to replace text in the template
+    #[error("Error while trying to replace in template file: {0}")]
+    CouldNotReplaceTemplate(#[from] templating::Error),
+}
+
+/// Width of a day column, in pixels
+const DAY_WIDTH: u32 = 200;
+/// Height of an hour, in pixels
+const HOUR_HEIGHT: u32 = 56;
+/// Width of the hour marks column, in pixels
+const LEFT_MARGIN: u32 = 50;
+/// Height of the date headers row, in pixels
+const TOP_MARGIN: u32 = 30;
+/// Approximate width of a character, in pixels
+const CHAR_WIDTH: u32 = 7;
+/// Height of a line of text, in pixels
+const LINE_HEIGHT: u32 = 14;
+/// Space between an event box and its day column borders, in pixels
+const PADDING: u32 = 3;
+
+#[allow(clippy::option_if_let_else)]
+fn get_template(template_path: Option<String>) -> Result<String, std::io::Error> {
+    match template_path {
+        None => Ok(include_str!("../../data/template.svg").to_string()),
+        Some(path) => std::fs::read_to_string(path),
+    }
+}
+
+/// Vertical position of a time of day
+const fn y_of(hour: u32, minute: u32, first_hour: u32) -> u32 {
+    TOP_MARGIN + (hour - first_hour) * HOUR_HEIGHT + minute * HOUR_HEIGHT / 60
+}
+
+/// Horizontal position of the left border of a day column
+const fn x_of(day: u32) -> u32 {
+    LEFT_MARGIN + day * DAY_WIDTH
+}
+
+/// Wrap a text on words to lines of at most `width` characters, keeping at most `max_lines` lines.
+/// The last line kept ends with `...` if some text was dropped.
+fn wrap(text: &str, width: usize, max_lines: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= width => {
+                line.push(' ');
+                *line += word;
+            }
+            _ => lines.push(word.chars().take(width).collect()),
+        }
+    }
+
+    if lines.len() > max_lines {
+        lines.truncate(max_lines.max(1));
+        if let Some(last) = lines.last_mut() {
+            let kept: String = last.chars().take(width.saturating_sub(3)).collect();
+            *last = kept + "...";
+        }
+    }
+    lines
+}
+
+/// Generate the hour marks and horizontal dividers
+fn hour_lines(first_hour: u32, last_hour: u32, day_count: u32) -> String {
+    let mut r = String::new();
+    for hour in first_hour..=last_hour {
+        let y = y_of(hour, 0, first_hour);
+        let _ = write!(
+            r,
+            r#"
+  <text class="hour" x="{}" y="{y}">{}:00</text>
+  <line class="grid" x1="{LEFT_MARGIN}" y1="{y}" x2="{}" y2="{y}"/>"#,
+            LEFT_MARGIN - 5,
+            hour % 24,
+            x_of(day_count),
+        );
+    }
+    r
+}
+
+/// Generate the vertical dividers and the date headers
+fn day_columns(
+    first_hour: u32,
+    last_hour: u32,
+    first_day: NaiveDate,
+    day_count: u32,
+    locale: Locale,
+) -> String {
+    let mut r = String::new();
+    let bottom = y_of(last_hour, 0, first_hour);
+    for day in 0..=day_count {
+        let _ = write!(
+            r,
+            r#"
+  <line class="grid" x1="{x}" y1="{top}" x2="{x}" y2="{bottom}"/>"#,
+            x = x_of(day),
+            top = TOP_MARGIN - 20,
+        );
+        if day < day_count {
+            let date = first_day + Duration::days(i64::from(day));
+            let _ = write!(
+                r,
+                r#"
+  <text class="date" x="{}" y="{}">{}</text>"#,
+                x_of(day) + DAY_WIDTH / 2,
+                TOP_MARGIN - 6,
+                locale.day(&date),
+            );
+        }
+    }
+    r
+}
+
+/// Generate a box in the calendar for a given event
+#[allow(clippy::cast_possible_truncation)]
+#[allow(clippy::cast_sign_loss)]
+fn event_box(e: &Event, first_day: NaiveDate, first_hour: u32, locale: Locale) -> String {
+    let day = (e.start_date.date_naive() - first_day).num_days() as u32;
+    let x = x_of(day) + PADDING;
+    let y = y_of(e.start_date.hour(), e.start_date.minute(), first_hour);
+    let width = DAY_WIDTH - 2 * PADDING;
+    let height = (e.duration * HOUR_HEIGHT / 60).max(LINE_HEIGHT);
+
+    let max_lines = (height / LINE_HEIGHT).max(1) as usize;
+    let lines = wrap(
+        // The short text is meant for LaTeX, where `~` is an unbreakable space
+        &e.short_text(locale).replace('~', "\u{a0}"),
+        ((width - 2 * PADDING) / CHAR_WIDTH) as usize,
+        max_lines,
+    );
+    // Center the text block vertically in the box
+    let text_top = y + height / 2 + LINE_HEIGHT / 2 - (lines.len() as u32 * LINE_HEIGHT) / 2 - 2;
+
+    let mut r = format!(
+        r#"
+  <g>
+    <title>{}</title>
+    <rect class="event {}" x="{x}" y="{y}" width="{width}" height="{height}" rx="4"/>
+    <text class="event-text" x="{}" y="{text_top}">"#,
+        escape(e.display_title(locale)),
+        e.event_type,
+        x + width / 2,
+    );
+    for (i, line) in lines.iter().enumerate() {
+        let _ = write!(
+            r,
+            r#"<tspan x="{}" dy="{}">{}</tspan>"#,
+            x + width / 2,
+            if i == 0 { 0 } else { LINE_HEIGHT },
+            escape(line)
+        );
+    }
+    r += "</text>\n  </g>";
+    r
+}
+
+impl CompilingPass<Vec<Event>, Options> for Pass {
+    type Residual = String;
+    type Error = Error;
+
+    fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
+        Self::apply_with(events, Options::default())
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::cast_sign_loss)]
+    fn apply_with(events: Vec<Event>, options: Options) -> Result<Self::Residual, Self::Error> {
+        let template = get_template(options.template_path)?;
+        // Get the bounding box to adjust the timetable shown (hours and days)
+        let bb = find_bounding_box(&events).ok_or(Error::NoEventProvided)?;
+
+        let first_hour = bb.up_left.hour();
+        let last_hour = end_hour(&events);
+        let first_day = bb.first_day()?.date_naive();
+        // Events ending at midnight end on the day they start
+        let last_day = events
+            .iter()
+            .map(|e| e.start_date.date_naive())
+            .max()
+            .unwrap_or(first_day);
+        let day_count = ((last_day - first_day).num_days() + 1) as u32;
+
+        let mut r = hour_lines(first_hour, last_hour, day_count);
+        r += &day_columns(first_hour, last_hour, first_day, day_count, options.locale);
+        for e in &events {
+            r += &event_box(e, first_day, first_hour, options.locale);
+        }
+
+        let t = templating::replace(&template, "WIDTH", &(x_of(day_count) + 10).to_string())?;
+        let t = templating::replace(
+            &t,
+            "HEIGHT",
+            &(y_of(last_hour, 0, first_hour) + 10).to_string(),
+        )?;
+        Ok(templating::replace(&t, "CALENDAR", &r)?)
+    }
+}
+
+#[test]
+fn test_wrap() {
+    assert_eq!(
+        wrap("a few short words", 7, 3),
+        vec!["a few", "short", "words"]
+    );
+    assert_eq!(wrap("a few short words", 7, 2), vec!["a few", "shor..."]);
+}
+
+#[test]
+fn test_midnight() {
+    let events = crate::passes::parser::ParseTimetable::apply(
+        "title: Concert\ndate: 2023-04-30 22:00\nduration: 60\n---\ntitle: Party\ndate: 2023-04-30 23:00\nduration: 60",
+    )
+    .unwrap();
+    let svg = Pass::apply_with(events, Options::default()).unwrap();
+
+    // An event ending at midnight keeps its last hour, and does not add the next day
+    assert!(svg.contains(">0:00</text>"));
+    assert!(svg.contains(&format!("width=\"{}\"", x_of(1) + 10)));
+    assert!(svg.contains(&format!("height=\"{}\"", y_of(24, 0, 22) + 10)));
+}
@@ -1,12 +1,13 @@
 //! Latex backends
 
-use std::str::FromStr;
+use std::{fmt::Write, str::FromStr};
 
 use chrono::{DateTime, Datelike, Duration, Local, Timelike};
 
 use crate::{
     event::{find_bounding_box, Event, InvalidDatetime},
-    passes::CompilingPass,
+    locale::Locale,
+    passes::{latexmk::event_marker, CompilingPass},
     templating,
 };
 
@@ -14,9 +15,12 @@ use crate::{
 pub struct Pass {}
 
 /// Options for the `TikZ` backend
+#[derive(Default, Clone)]
 pub struct Options {
     /// Path to the template file. If not set, the default template (`data/template_tikz.tex`) will be used.
     pub template_path: Option<String>,
+    /// Language of the dates and of the words joining speakers
+    pub locale: Locale,
 }
 
 /// Error occuring when compiling an event list to `TikZ`.
@@ -74,9 +78,11 @@ fn hour_marks(first_hour: u32, last_hour: u32) -> String {
 }
 
 /// Generate a tikz node in the calendar for a given event
-fn tikz_node(e: &Event, up_left_day: u32) -> String {
-    r"
-    \node[".to_owned()
+fn tikz_node(e: &Event, up_left_day: u32, locale: Locale) -> String {
+    "\n    ".to_owned()
+        + &event_marker(e)
+        + r"
+    \node["
         // declare the event type for the format
         + &format!("{}", e.event_type)
         + "={"
@@ -96,7 +102,7 @@ fn tikz_node(e: &Event, up_left_day: u32) -> String {
         )
         + ") {"
         // Create the string to fill up the event block
-        + &e.short_text()
+        + &e.short_text(locale)
         + "};"
 }
 
@@ -116,21 +122,23 @@ fn day_dividers(first_hour: u32, last_hour: u32, day_count: u32) -> String {
 }
 
 /// Generate the date headers at the top of the columns
-fn date_headers(first_hour: u32, day_count: u32, up_left: DateTime<Local>) -> String {
+fn date_headers(
+    first_hour: u32,
+    day_count: u32,
+    up_left: DateTime<Local>,
+    locale: Locale,
+) -> String {
     let mut r = String::new();
     // Display the date headers
     for i in 0..day_count {
         let col = i + 1;
         r += r"
     \node[anchor=south] at (";
-        r += &format!("{col}");
+        let _ = write!(r, "{col}");
         r += r".5, ";
-        r += &format!("{}", first_hour - 1);
+        let _ = write!(r, "{}", first_hour - 1);
         r += ".5) {";
-        r += &format!(
-            "{}",
-            (up_left + Duration::days(i64::from(i))).format("%A, %B %e")
-        );
+        r += &locale.day(&(up_left + Duration::days(i64::from(i))));
         r += "};";
     }
     r
@@ -141,12 +149,7 @@ impl CompilingPass<Vec<Event>> for Pass {
     type Error = Error;
 
     fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
-        Self::apply_with(
-            events,
-            Options {
-                template_path: None,
-            },
-        )
+        Self::apply_with(events, Options::default())
     }
 }
 
@@ -155,12 +158,7 @@ impl CompilingPass<Vec<Event>, Options> for Pass {
     type Error = Error;
 
     fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
-        Self::apply_with(
-            events,
-            Options {
-                template_path: None,
-            },
-        )
+        Self::apply_with(events, Options::default())
     }
 
     // TODO Programmatically generate formats (tikzset)?
@@ -181,11 +179,11 @@ impl CompilingPass<Vec<Event>, Options> for Pass {
         r += &hour_dividers(first_hour, last_hour, day_count);
 
         r += &day_dividers(first_hour, last_hour, day_count);
-        r += &date_headers(first_hour, day_count, bb.up_left);
+        r += &date_headers(first_hour, day_count, bb.up_left, options.locale);
 
         // Display all our event nodes
         for e in events {
-            r += &tikz_node(&e, bb.up_left.day());
+            r += &tikz_node(&e, bb.up_left.day(), options.locale);
         }
 
         Ok(templating::replace(&template, "CALENDAR", &r)?)
@@ -0,0 +1,83 @@
+//! Choice of the language of the titles and descriptions
+
+use isolang::Language;
+
+use crate::{event::Event, passes::CompilingPass};
+
+/// Replaces the titles and descriptions of the events by their translations in a given language,
+/// keeping the original ones when there is no translation
+pub struct Pass {}
+
+/// Options for the translation pass
+#[derive(Default, Clone)]
+pub struct Options {
+    /// Language of the titles and descriptions. If not set, the events are left as is, with all
+    /// their translations.
+    pub language: Option<Language>,
+}
+
+impl CompilingPass<Vec<Event>, Options> for Pass {
+    type Residual = Vec<Event>;
+    type Error = std::convert::Infallible;
+
+    fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
+        Ok(events)
+    }
+
+    fn apply_with(mut events: Vec<Event>, options: Options) -> Result<Self::Residual, Self::Error> {
+        let Some(language) = options.language else {
+            return Ok(events);
+        };
+        // The events are then only in one language
+        for e in &mut events {
+            let title = std::mem::take(&mut e.titles).remove(&language);
+            let description = std::mem::take(&mut e.descriptions).remove(&language);
+            // The backends hyphenate the text in the first language of the event
+            if title.is_some() || description.is_some() {
+                e.languages.retain(|l| *l != language);
+                e.languages.insert(0, language);
+            }
+            if let Some(title) = title {
+                e.title = title;
+            }
+            if description.is_some() {
+                e.description = description;
+            }
+        }
+        Ok(events)
+    }
+}
+
+#[test]
+fn test_translate() {
+    use std::str::FromStr;
+
+    let event = Event::from_str(
+        "title: Cryptographie\ntitle.en: Cryptography\ndate: 2023-04-27 10:00\nduration: 30\nlang: fr, en\n\nEn français\n\n::: en\nIn English\n\n::: de\nAuf Deutsch\n:::\n\\::: fr",
+    )
+    .unwrap();
+    assert_eq!(event.field("title.en").as_deref(), Some("Cryptography"));
+    assert_eq!(event.description.as_deref(), Some("En français\n\n::: fr"));
+    assert_eq!(
+        event.descriptions.get(&Language::Deu).map(String::as_str),
+        Some("Auf Deutsch")
+    );
+
+    let translate = |language| {
+        let Ok(events) = Pass::apply_with(vec![event.clone()], Options { language });
+        events.into_iter().next().unwrap()
+    };
+    let english = translate(Some(Language::Eng));
+    assert_eq!(english.title, "Cryptography");
+    assert_eq!(english.description.as_deref(), Some("In English"));
+    assert!(english.titles.is_empty() && english.descriptions.is_empty());
+    assert_eq!(english.languages, [Language::Eng, Language::Fra]);
+
+    // Without a translation, the original is kept
+    let german = translate(Some(Language::Deu));
+    assert_eq!(german.title, "Cryptographie");
+    assert_eq!(german.description.as_deref(), Some("Auf Deutsch"));
+    assert_eq!(german.languages[0], Language::Deu);
+
+    assert_eq!(translate(None).titles.len(), 1);
+}
@@ -0,0 +1,275 @@
+//! Typst backends, producing the timetable grid and the abstracts booklet
+//!
+//! The generated documents are compiled with `typst compile`.
+
+mod math;
+
+use std::{fmt::Write, str::FromStr};
+
+use chrono::{DateTime, Days, Local, NaiveDate, Timelike};
+
+use crate::{
+    event::{end_hour, find_bounding_box, Event, InvalidDatetime, Type},
+    language,
+    locale::Locale,
+    markup::{Block, Inline, Markup},
+    passes::CompilingPass,
+    templating,
+};
+
+/// Backend outputing events to a Typst document containing a timetable
+pub struct Timetable {}
+
+/// Backend outputing events to a Typst document containing the abstracts
+pub struct Abstracts {}
+
+/// Options for the Typst backends
+#[derive(Default, Clone)]
+pub struct Options {
+    /// Path to the template file. If not set, the default template (`data/template_typst.typ` or
+    /// `data/template_abstypst.typ`) will be used.
+    pub template_path: Option<String>,
+    /// Language of the document, its dates and the words joining speakers
+    pub locale: Locale,
+}
+
+/// Error occuring when compiling an event list to Typst.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The event could not be parsed.
+    #[error(transparent)]
+    CouldNotParseEvent(#[from] <Event as FromStr>::Err),
+    /// The list of events was empty.
+    #[error("no event was provided")]
+    NoEventProvided,
+    /// The datetime of either the first day or last day of the bounding box is not valid.
+    #[error(transparent)]
+    InvalidDatetime(#[from] InvalidDatetime),
+    /// An error occurred while trying to read the template file
+    #[error("Error while trying to read the template file: {0}")]
+    CouldNotReadTemplate(#[from] std::io::Error),
+    /// An error occurred while trying to replace text in the template
+    #[error("Error while trying to replace in template file: {0}")]
+    CouldNotReplaceTemplate(#[from] templating::Error),
+}
+
+fn get_template(template_path: Option<String>, default: &str) -> Result<String, std::io::Error> {
+    template_path.map_or_else(|| Ok(default.to_owned()), std::fs::read_to_string)
+}
+
+/// Characters that have a special meaning in Typst markup
+const SPECIAL_CHARACTERS: &str = r"\#*_`$<>@[]~/=-+";
+
+/// Escape the characters that have a special meaning in Typst markup
+#[must_use]
+pub fn escape(text: &str) -> String {
+    let mut r = String::with_capacity(text.len());
+    for c in text.chars() {
+        if SPECIAL_CHARACTERS.contains(c) {
+            r.push('\\');
+        }
+        r.push(c);
+    }
+    r
+}
+
+/// Write a Typst string literal
+fn string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', r"\\").replace('"', "\\\""))
+}
+
+fn inlines_to_typst(inlines: &[Inline]) -> String {
+    inlines.iter().map(inline_to_typst).collect()
+}
+
+fn inline_to_typst(inline: &Inline) -> String {
+    match inline {
+        Inline::Text(text) => escape(text),
+        Inline::Emphasis(content) => format!("#emph[{}]", inlines_to_typst(content)),
+        Inline::Strong(content) => format!("#strong[{}]", inlines_to_typst(content)),
+        Inline::Code(code) => format!("#raw({})", string(code)),
+        Inline::Link(url, content) => {
+            format!("#link({})[{}]", string(url), inlines_to_typst(content))
+        }
+        // Spaces inside the dollars make an equation a block
+        Inline::Math(math) => format!("${}$", math::latex_to_typst(math)),
+        Inline::DisplayMath(math) => format!("$ {} $", math::latex_to_typst(math)),
+        Inline::SoftBreak => "\n".to_owned(),
+        Inline::HardBreak => "\\\n".to_owned(),
+    }
+}
+
+fn blocks_to_typst(blocks: &[Block]) -> String {
+    blocks
+        .iter()
+        .map(block_to_typst)
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn block_to_typst(block: &Block) -> String {
+    match block {
+        Block::Paragraph(content) => inlines_to_typst(content),
+        Block::Heading(_, content) => format!(
+            "#heading(level: 3, outlined: false)[{}]",
+            inlines_to_typst(content)
+        ),
+        Block::List(start, items) => {
+            let mut r = start.map_or_else(
+                || "#list".to_owned(),
+                |start| format!("#enum(start: {start})"),
+            );
+            for item in items {
+                let _ = write!(r, "[{}]", blocks_to_typst(item));
+            }
+            r
+        }
+        Block::Quote(content) => {
+            format!("#quote(block: true)[{}]", blocks_to_typst(content))
+        }
+        Block::Code(code) => format!("#raw(block: true, {})", string(code)),
+        Block::Rule => "#line(length: 100%)".to_owned(),
+    }
+}
+
+/// Generate the Typst dictionary describing an event of the timetable
+#[allow(clippy::cast_precision_loss)]
+fn event_entry(e: &Event, first_day: NaiveDate, locale: Locale) -> String {
+    let day = (e.start_date.date_naive() - first_day).num_days();
+    let start = f64::from(e.start_date.hour()) + f64::from(e.start_date.minute()) / 60.;
+    format!(
+        "\n  (day: {day}, start: {start:.4}, duration: {:.4}, kind: {}, body: [{}]),",
+        f64::from(e.duration) / 60.,
+        string(&e.event_type.to_string()),
+        // The short text is meant for LaTeX, where `~` is an unbreakable space, as in Typst
+        escape(&e.short_text(locale)).replace(r"\~", "~"),
+    )
+}
+
+impl CompilingPass<Vec<Event>, Options> for Timetable {
+    type Residual = String;
+    type Error = Error;
+
+    fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
+        Self::apply_with(events, Options::default())
+    }
+
+    fn apply_with(events: Vec<Event>, options: Options) -> Result<Self::Residual, Self::Error> {
+        let template = get_template(
+            options.template_path,
+            include_str!("../../data/template_typst.typ"),
+        )?;
+        let locale = options.locale;
+        // Get the bounding box to adjust the timetable shown (hours and days)
+        let bb = find_bounding_box(&events).ok_or(Error::NoEventProvided)?;
+        let first_day = bb.first_day()?.date_naive();
+        // Events ending at midnight end on the day they start
+        let last_day = events
+            .iter()
+            .map(|e| e.start_date.date_naive())
+            .max()
+            .unwrap_or(first_day);
+
+        let mut r = format!(
+            "#let first-hour = {}\n#let last-hour = {}\n#let days = (",
+            bb.up_left.hour(),
+            end_hour(&events),
+        );
+        for day in first_day.iter_days().take_while(|d| *d <= last_day) {
+            let _ = write!(r, "[{}], ", locale.day(&day));
+        }
+        r += ")\n#let events = (";
+        for e in &events {
+            r += &event_entry(e, first_day, locale);
+        }
+        r += "\n)";
+
+        let t = templating::replace(&template, "LANG", locale.name())?;
+        Ok(templating::replace(&t, "CALENDAR", &r)?)
+    }
+}
+
+fn day_delimiter(day: &DateTime<Local>, locale: Locale) -> String {
+    format!("= {}\n\n", locale.day(day))
+}
+
+fn talk(e: &Event, locale: Locale) -> String {
+    let mut r = format!("== {}\n\n", escape(e.display_title(locale)));
+    let _ = write!(r, "_{}", e.start_date.time().format("%H:%M"));
+    if !e.speakers.is_empty() {
+        let _ = write!(r, " \\- {}", escape(&e.speakers_string()));
+    }
+    r += "_\n\n";
+    if let Some(d) = &e.description {
+        r += &blocks_to_typst(&Markup::parse(d).0);
+        r += "\n\n";
+    }
+    // Set the main language of the talk, for hyphenation and quotes
+    e.languages.first().map_or_else(
+        || r.clone(),
+        |l| format!("#text(lang: \"{}\")[\n{r}]\n\n", language::code(*l)),
+    )
+}
+
+impl CompilingPass<Vec<Event>, Options> for Abstracts {
+    type Residual = String;
+    type Error = Error;
+
+    fn apply(events: Vec<Event>) -> Result<Self::Residual, Self::Error> {
+        Self::apply_with(events, Options::default())
+    }
+
+    fn apply_with(mut events: Vec<Event>, options: Options) -> Result<Self::Residual, Self::Error> {
+        events.sort_by_key(|e| e.start_date);
+        let template = get_template(
+            options.template_path,
+            include_str!("../../data/template_abstypst.typ"),
+        )?;
+
+        let bb = find_bounding_box(&events).ok_or(Error::NoEventProvided)?;
+        let mut day = bb.first_day()? - Days::new(1);
+
+        let mut r = String::new();
+        for e in &events {
+            if e.start_date.date_naive() > day.date_naive() {
+                day = e.start_date;
+                r += &day_delimiter(&day, options.locale);
+            }
+            if matches!(e.event_type, Type::Talk | Type::Fun) {
+                r += &talk(e, options.locale);
+            }
+        }
+
+        let (first_day, last_day) = (bb.first_day()?, bb.last_day()?);
+        let locale = options.locale;
+        let t = templating::replace(&template, "LANG", locale.name())?;
+        let t = templating::replace(&t, "DATES", &locale.date_range(&first_day, &last_day))?;
+        let t = templating::replace(&t, "BEGIN_DATE", &locale.day(&first_day))?;
+        let t = templating::replace(&t, "END_DATE", &locale.day(&last_day))?;
+        Ok(templating::replace(&t, "ABSTRACTS", &r)?)
+    }
+}
+
+#[test]
+fn test_markup_to_typst() {
+    let markup = Markup::parse(
+        "Some *emphasis* on `a #b`, see [here](https://a.b/\"c\"), $x^2$.\n\n1. one\n2. two",
+    );
+    assert_eq!(
+        blocks_to_typst(&markup.0),
+        "Some #emph[emphasis] on #raw(\"a #b\"), see #link(\"https://a.b/\\\"c\\\"\")[here], $x^(2)$.\n\n#enum(start: 1)[one][two]"
+    );
+}
+
+#[test]
+fn test_midnight() {
+    let events = crate::passes::parser::ParseTimetable::apply(
+        "title: Concert\ndate: 2023-04-30 22:00\nduration: 60\n---\ntitle: Party\ndate: 2023-04-30 23:00\nduration: 60",
+    )
+    .unwrap();
+    let typst = Timetable::apply_with(events, Options::default()).unwrap();
+
+    // An event ending at midnight keeps its last hour, and does not add the next day
+    assert!(typst.contains("#let first-hour = 22\n#let last-hour = 24\n"));
+    assert!(typst.contains("Sunday, April 30") && !typst.contains("Monday, May 1"));
+}
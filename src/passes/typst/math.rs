@@ -0,0 +1,280 @@
+//! Conversion of `LaTeX` math, as written in descriptions, to Typst math
+//!
+//! Only the common commands are converted: fractions, roots, text, fonts, accents, Greek letters,
+//! operators and relations. Other commands are shown as their source.
+
+use std::{iter::Peekable, str::Chars};
+
+/// Commands written as a Typst symbol or function of the same name
+const SAME_NAME: &[&str] = &[
+    "alpha", "beta", "gamma", "delta", "zeta", "eta", "theta", "iota", "kappa", "lambda", "mu",
+    "nu", "xi", "pi", "rho", "sigma", "tau", "upsilon", "chi", "psi", "omega", "Gamma", "Delta",
+    "Theta", "Lambda", "Xi", "Pi", "Sigma", "Upsilon", "Phi", "Psi", "Omega", "arccos", "arcsin",
+    "arctan", "arg", "cos", "cosh", "cot", "coth", "csc", "deg", "det", "dim", "exp", "gcd", "inf",
+    "ker", "lim", "liminf", "limsup", "ln", "log", "max", "min", "sec", "sin", "sinh", "sup",
+    "tan", "tanh", "sum", "nabla", "times", "approx", "equiv", "subset", "supset", "forall",
+    "exists", "emptyset", "quad",
+];
+
+/// Commands written as a symbol
+const SYMBOLS: &[(&str, &str)] = &[
+    ("epsilon", "ϵ"),
+    ("varepsilon", "ε"),
+    ("phi", "ϕ"),
+    ("varphi", "φ"),
+    ("vartheta", "ϑ"),
+    ("cdot", "⋅"),
+    ("pm", "±"),
+    ("mp", "∓"),
+    ("le", "≤"),
+    ("leq", "≤"),
+    ("ge", "≥"),
+    ("geq", "≥"),
+    ("ne", "≠"),
+    ("neq", "≠"),
+    ("ll", "≪"),
+    ("gg", "≫"),
+    ("sim", "∼"),
+    ("simeq", "≃"),
+    ("propto", "∝"),
+    ("infty", "∞"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("leftarrow", "←"),
+    ("Rightarrow", "⇒"),
+    ("Leftarrow", "⇐"),
+    ("iff", "⇔"),
+    ("mapsto", "↦"),
+    ("int", "∫"),
+    ("oint", "∮"),
+    ("prod", "∏"),
+    ("partial", "∂"),
+    ("in", "∈"),
+    ("notin", "∉"),
+    ("subseteq", "⊆"),
+    ("supseteq", "⊇"),
+    ("cup", "∪"),
+    ("cap", "∩"),
+    ("setminus", "∖"),
+    ("neg", "¬"),
+    ("land", "∧"),
+    ("wedge", "∧"),
+    ("lor", "∨"),
+    ("vee", "∨"),
+    ("circ", "∘"),
+    ("mid", "∣"),
+    ("langle", "⟨"),
+    ("rangle", "⟩"),
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("cdots", "⋯"),
+    ("qquad", "wide"),
+    (",", "thin"),
+    (":", "med"),
+    (";", "thick"),
+    ("!", ""),
+    (" ", "space"),
+    ("\\", "\\ "),
+    ("{", "\\{"),
+    ("}", "\\}"),
+    ("|", "‖"),
+    ("%", "%"),
+    ("&", "\\&"),
+    ("#", "\\#"),
+    ("$", "\\$"),
+    ("_", "\\_"),
+];
+
+/// Commands taking one argument, written as a Typst function
+const FUNCTIONS: &[(&str, &str)] = &[
+    ("sqrt", "sqrt"),
+    ("mathbb", "bb"),
+    ("mathbf", "bold"),
+    ("boldsymbol", "bold"),
+    ("mathit", "italic"),
+    ("mathcal", "cal"),
+    ("mathfrak", "frak"),
+    ("mathsf", "sans"),
+    ("mathtt", "mono"),
+    ("hat", "hat"),
+    ("widehat", "hat"),
+    ("bar", "overline"),
+    ("overline", "overline"),
+    ("underline", "underline"),
+    ("vec", "arrow"),
+    ("tilde", "tilde"),
+    ("widetilde", "tilde"),
+    ("dot", "dot"),
+    ("ddot", "dot.double"),
+];
+
+/// Commands taking one argument written as text
+const TEXT_FUNCTIONS: &[&str] = &["text", "textrm", "mathrm", "operatorname", "mbox"];
+
+/// Commands that only change the layout in `LaTeX`, left to Typst
+const IGNORED: &[&str] = &[
+    "left",
+    "right",
+    "big",
+    "Big",
+    "bigg",
+    "Bigg",
+    "displaystyle",
+    "textstyle",
+    "limits",
+    "nolimits",
+];
+
+/// Characters that have a special meaning in Typst math but not in `LaTeX` math
+const SPECIAL_CHARACTERS: &str = "#$\"/,;@";
+
+/// Convert `LaTeX` math to Typst math, without the delimiters
+#[must_use]
+pub fn latex_to_typst(math: &str) -> String {
+    convert(&mut math.chars().peekable(), false)
+        .trim()
+        .to_owned()
+}
+
+/// Append a Typst token, separated from the previous one if they would be read as a single
+/// identifier or number
+fn push(r: &mut String, token: &str) {
+    if let (Some(last), Some(first)) = (r.chars().last(), token.chars().next()) {
+        let both_digits = last.is_ascii_digit() && first.is_ascii_digit();
+        if last.is_alphanumeric() && first.is_alphanumeric() && !both_digits {
+            r.push(' ');
+        }
+    }
+    r.push_str(token);
+}
+
+/// Read the name of a command, after its backslash
+fn command(chars: &mut Peekable<Chars>) -> String {
+    let mut name = String::new();
+    while let Some(&c) = chars.peek() {
+        if !c.is_ascii_alphabetic() {
+            break;
+        }
+        name.push(c);
+        chars.next();
+    }
+    if name.is_empty() {
+        name.extend(chars.next());
+    }
+    name
+}
+
+/// Skip the spaces before an argument
+fn skip_spaces(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+/// Read the source of an argument: a group in braces, or a single character or command
+fn raw_argument(chars: &mut Peekable<Chars>) -> String {
+    skip_spaces(chars);
+    match chars.next() {
+        Some('{') => {
+            let mut depth = 0;
+            let mut r = String::new();
+            for c in chars.by_ref() {
+                match c {
+                    '{' => depth += 1,
+                    '}' if depth == 0 => break,
+                    '}' => depth -= 1,
+                    _ => (),
+                }
+                r.push(c);
+            }
+            r
+        }
+        Some('\\') => format!("\\{}", command(chars)),
+        Some(c) => c.to_string(),
+        None => String::new(),
+    }
+}
+
+/// Read and convert an argument
+fn argument(chars: &mut Peekable<Chars>) -> String {
+    latex_to_typst(&raw_argument(chars))
+}
+
+/// Convert `LaTeX` math up to the end, or up to the closing brace of the group if `group` is set
+fn convert(chars: &mut Peekable<Chars>, group: bool) -> String {
+    let mut r = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '}' if group => break,
+            '{' => push(&mut r, &convert(chars, true)),
+            '^' | '_' => {
+                r.push(c);
+                r.push('(');
+                r += &argument(chars);
+                r.push(')');
+            }
+            '\\' => convert_command(&command(chars), chars, &mut r),
+            '~' => r.push(' '),
+            c if SPECIAL_CHARACTERS.contains(c) => {
+                r.push('\\');
+                r.push(c);
+            }
+            c if c.is_whitespace() => {
+                if !r.ends_with(' ') {
+                    r.push(' ');
+                }
+            }
+            c => push(&mut r, &c.to_string()),
+        }
+    }
+    r
+}
+
+/// Convert a command, reading its arguments
+fn convert_command(name: &str, chars: &mut Peekable<Chars>, r: &mut String) {
+    if name == "frac" || name == "dfrac" || name == "tfrac" {
+        let numerator = argument(chars);
+        let denominator = argument(chars);
+        push(r, &format!("frac({numerator}, {denominator})"));
+    } else if name == "sqrt" && chars.peek() == Some(&'[') {
+        chars.next();
+        let index: String = chars.by_ref().take_while(|&c| c != ']').collect();
+        let radicand = argument(chars);
+        push(r, &format!("root({}, {radicand})", latex_to_typst(&index)));
+    } else if let Some((_, function)) = FUNCTIONS.iter().find(|(n, _)| *n == name) {
+        push(r, &format!("{function}({})", argument(chars)));
+    } else if TEXT_FUNCTIONS.contains(&name) {
+        let text = raw_argument(chars);
+        push(
+            r,
+            &format!(
+                "upright(\"{}\")",
+                text.replace('\\', r"\\").replace('"', "\\\"")
+            ),
+        );
+    } else if IGNORED.contains(&name) {
+        // `\left.` and `\right.` have no delimiter
+        if name == "left" || name == "right" {
+            skip_spaces(chars);
+            chars.next_if_eq(&'.');
+        }
+    } else if SAME_NAME.contains(&name) {
+        push(r, name);
+    } else if let Some((_, symbol)) = SYMBOLS.iter().find(|(n, _)| *n == name) {
+        push(r, symbol);
+    } else {
+        push(r, &format!("\"\\\\{name}\""));
+    }
+}
+
+#[test]
+fn test_latex_to_typst() {
+    assert_eq!(latex_to_typst("x^2"), "x^(2)");
+    assert_eq!(latex_to_typst("ab_{i,j}"), "a b_(i\\,j)");
+    assert_eq!(
+        latex_to_typst(r"\frac{\alpha}{2} \le \sqrt[3]{x} + \text{if } y"),
+        "frac(alpha, 2) ≤ root(3, x) + upright(\"if \") y"
+    );
+    assert_eq!(
+        latex_to_typst(r"\left( \sum_{n=1}^\infty \mathbb{R}^n \right.\foo"),
+        "( sum_(n=1)^(∞) bb(R)^(n) \"\\\\foo\""
+    );
+}
@@ -0,0 +1,756 @@
+//! Pipelines of passes, described by their names
+//!
+//! A pipeline is written as pass names separated by `|`, such as
+//! `seri | recurrence | filter:tag:keynote | lint | tikz | latexmk`. The first pass reads the
+//! input, the others transform or analyse the events, and the last ones write the output. A pass
+//! can be given an argument after a colon. Passes are looked up in a [`Registry`], which maps
+//! their names to implementations of [`CompilingPass`](crate::passes::CompilingPass).
+
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{
+    compiler::{self, Options},
+    event::Event,
+    language,
+    passes::{
+        abstex, agenda, csv, filter,
+        html::{HTMLBackend, HTMLBackendOptions},
+        ics,
+        lint::{self, Warning},
+        parser::ParseTimetable,
+        pentabarf, plugin, recurrence, seri, svg, tikz, translate, typst, CompilingPass, PassInput,
+    },
+};
+
+/// Data flowing between the passes of a pipeline
+#[derive(Debug)]
+pub enum Value {
+    /// A text document, such as the input or a generated `LaTeX` document
+    Text(String),
+    /// Timetable events
+    Events(Vec<Event>),
+    /// A binary document, such as a PDF
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    /// Name of the kind of value, for error messages
+    #[must_use]
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::Text(_) => "text",
+            Self::Events(_) => "events",
+            Self::Bytes(_) => "bytes",
+        }
+    }
+}
+
+/// Role of a pass in a pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Reads the input text into events
+    Input,
+    /// Transforms the events
+    Transform,
+    /// Checks the events without changing them, reporting warnings
+    Analysis,
+    /// Writes the events, or a previous output, to an output document
+    Backend,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Input => "input",
+            Self::Transform => "transform",
+            Self::Analysis => "analysis",
+            Self::Backend => "backend",
+        })
+    }
+}
+
+/// Error occurring when building or running a pipeline.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The pipeline has no pass
+    #[error("the pipeline is empty")]
+    Empty,
+    /// A line of the pipeline has a quote that is never closed
+    #[error("`{0}` has a quote that is never closed")]
+    UnclosedQuote(String),
+    /// No pass is registered with this name
+    #[error("unknown {kind} pass `{name}`")]
+    UnknownPass {
+        /// The role of the pass in the pipeline
+        kind: &'static str,
+        /// The name of the pass
+        name: String,
+    },
+    /// The argument of a pass is not valid
+    #[error("invalid argument for pass `{name}`: {message}")]
+    InvalidArgument {
+        /// The name of the pass
+        name: String,
+        /// Why the argument is not valid
+        message: String,
+    },
+    /// A pass was given a value it cannot process
+    #[error("pass `{name}` expects {expected}, but was given {found}")]
+    UnexpectedValue {
+        /// The name of the pass
+        name: String,
+        /// The kind of value expected
+        expected: &'static str,
+        /// The kind of value given
+        found: &'static str,
+    },
+    /// A pass failed
+    #[error("pass `{name}` failed: {source}")]
+    Pass {
+        /// The name of the pass
+        name: String,
+        /// The underlying error
+        #[source]
+        source: compiler::Error,
+    },
+}
+
+/// What the passes report besides their result
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    /// The warnings of the analysis passes
+    pub warnings: Vec<Warning>,
+    /// What the plugins wrote to their standard error
+    pub messages: Vec<String>,
+}
+
+/// A pass ready to be run, with its argument and options
+pub type Stage = Box<dyn Fn(Value, &mut Diagnostics) -> Result<Value, compiler::Error>>;
+
+/// Builds a stage from the argument of the pass and the compiler options
+pub type Builder = fn(Option<&str>, &Options) -> Result<Stage, String>;
+
+/// A pass known by a [`Registry`]
+pub struct Entry {
+    /// Role of the pass
+    pub kind: Kind,
+    /// Kind of value taken by the pass
+    pub input: &'static str,
+    /// Short description, shown when listing passes
+    pub description: &'static str,
+    /// Builds the pass
+    pub build: Builder,
+}
+
+/// Named passes, usable in pipelines
+///
+/// Input passes have their own names, so that `csv` can both read and write CSV.
+pub struct Registry {
+    inputs: BTreeMap<String, Entry>,
+    passes: BTreeMap<String, Entry>,
+}
+
+/// Build a stage that takes events
+fn on_events(
+    stage: impl Fn(Vec<Event>, &mut Diagnostics) -> Result<Value, compiler::Error> + 'static,
+) -> Stage {
+    Box::new(move |value, diagnostics| match value {
+        Value::Events(events) => stage(events, diagnostics),
+        // Checked by `Pipeline::run` before calling the stage
+        other => Ok(other),
+    })
+}
+
+/// Build a stage that takes text
+fn on_text(stage: impl Fn(&str) -> Result<Value, compiler::Error> + 'static) -> Stage {
+    Box::new(move |value, _| match value {
+        Value::Text(text) => stage(&text),
+        other => Ok(other),
+    })
+}
+
+/// The lines written by a plugin to its standard error
+fn plugin_messages(stderr: &str) -> impl Iterator<Item = String> + '_ {
+    stderr.lines().map(str::to_owned)
+}
+
+impl Registry {
+    /// A registry without any pass
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self {
+            inputs: BTreeMap::new(),
+            passes: BTreeMap::new(),
+        }
+    }
+
+    /// Register a pass under a name, replacing any pass of the same role and name
+    pub fn register(&mut self, name: &str, entry: Entry) {
+        let map = if entry.kind == Kind::Input {
+            &mut self.inputs
+        } else {
+            &mut self.passes
+        };
+        map.insert(name.to_owned(), entry);
+    }
+
+    /// The registered passes, input passes first
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &Entry)> {
+        self.inputs.iter().chain(self.passes.iter())
+    }
+
+    /// Find a pass by its role and name
+    fn get(&self, input: bool, name: &str) -> Result<&Entry, Error> {
+        let (map, kind) = if input {
+            (&self.inputs, "input")
+        } else {
+            (&self.passes, "transform, analysis or backend")
+        };
+        map.get(name).ok_or_else(|| Error::UnknownPass {
+            kind,
+            name: name.to_owned(),
+        })
+    }
+}
+
+/// Build a stage running the pass `P` on events with the options `options`, and wrapping its
+/// result with `value`
+fn events_pass<P, C>(options: C, value: fn(P::Residual) -> Value) -> Stage
+where
+    P: CompilingPass<Vec<Event>, C>,
+    P::Residual: 'static,
+    C: Clone + 'static,
+    compiler::Error: From<P::Error>,
+{
+    on_events(move |events, _| Ok(value(events.chain_pass_with::<P, C>(options.clone())?)))
+}
+
+/// Read the argument of the `plugin` and `render` passes
+fn plugin_options(argument: Option<&str>) -> Result<plugin::Options, String> {
+    argument
+        .unwrap_or_default()
+        .parse()
+        .map_err(|e| format!("{e}"))
+}
+
+const TEXT: &str = "text";
+const EVENTS: &str = "events";
+
+/// A pass of the registry, with its name
+const fn builtin(
+    name: &'static str,
+    kind: Kind,
+    input: &'static str,
+    description: &'static str,
+    build: Builder,
+) -> (&'static str, Entry) {
+    (
+        name,
+        Entry {
+            kind,
+            input,
+            description,
+            build,
+        },
+    )
+}
+
+/// The built-in passes, with their names
+#[allow(clippy::too_many_lines)]
+fn builtins() -> Vec<(&'static str, Entry)> {
+    vec![
+        builtin(
+            "seri",
+            Kind::Input,
+            TEXT,
+            "Parse the Seri language",
+            |_, _| {
+                Ok(on_text(|text| {
+                    Ok(Value::Events(text.chain_pass::<ParseTimetable>()?))
+                }))
+            },
+        ),
+        builtin(
+            "csv",
+            Kind::Input,
+            TEXT,
+            "Read a CSV spreadsheet, one event per row",
+            |_, options| {
+                let csv = options.csv.clone();
+                Ok(on_text(move |text| {
+                    Ok(Value::Events(
+                        text.chain_pass_with::<csv::Import, csv::Options>(csv.clone())?,
+                    ))
+                }))
+            },
+        ),
+        builtin(
+            "ics",
+            Kind::Input,
+            TEXT,
+            "Read an iCalendar document",
+            |_, _| {
+                Ok(on_text(|text| {
+                    Ok(Value::Events(text.chain_pass::<ics::Import>()?))
+                }))
+            },
+        ),
+        builtin(
+            "recurrence",
+            Kind::Transform,
+            EVENTS,
+            "Expand recurring events",
+            |_, _| Ok(events_pass::<recurrence::Pass, _>((), Value::Events)),
+        ),
+        builtin(
+            "filter",
+            Kind::Transform,
+            EVENTS,
+            "Keep the events matching the argument, or the filters of the options",
+            |argument, options| {
+                let filter = match argument {
+                    Some(predicate) => filter::Options {
+                        predicates: vec![predicate.parse().map_err(|e| format!("{e}"))?],
+                    },
+                    None => options.filter.clone(),
+                };
+                Ok(events_pass::<filter::Pass, _>(filter, Value::Events))
+            },
+        ),
+        builtin(
+            "translate",
+            Kind::Transform,
+            EVENTS,
+            "Use the titles and descriptions in the language given as argument, or in the options",
+            |argument, options| {
+                let translate =
+                    match argument {
+                        Some(code) => translate::Options {
+                            language: Some(language::parse(code).ok_or_else(|| {
+                                format!("`{code}` is not an ISO 639 language code")
+                            })?),
+                        },
+                        None => options.translate.clone(),
+                    };
+                Ok(events_pass::<translate::Pass, _>(translate, Value::Events))
+            },
+        ),
+        builtin(
+            "plugin",
+            Kind::Transform,
+            EVENTS,
+            "Transform the events with the command given as argument, through JSON",
+            |argument, _| {
+                let plugin = plugin_options(argument)?;
+                Ok(on_events(move |events, diagnostics| {
+                    let output = events
+                        .chain_pass_with::<plugin::Transform, plugin::Options>(plugin.clone())?;
+                    diagnostics.messages.extend(plugin_messages(&output.stderr));
+                    Ok(Value::Events(output.value))
+                }))
+            },
+        ),
+        builtin(
+            "lint",
+            Kind::Analysis,
+            EVENTS,
+            "Warn about overlaps, talks without speakers and missing durations",
+            |_, _| {
+                Ok(on_events(|events, diagnostics| {
+                    let Ok(found) = lint::Pass::apply(&events);
+                    diagnostics.warnings.extend(found);
+                    Ok(Value::Events(events))
+                }))
+            },
+        ),
+        builtin(
+            "render",
+            Kind::Backend,
+            EVENTS,
+            "Document written by the command given as argument, from JSON",
+            |argument, _| {
+                let plugin = plugin_options(argument)?;
+                Ok(on_events(move |events, diagnostics| {
+                    let output = events
+                        .chain_pass_with::<plugin::Render, plugin::Options>(plugin.clone())?;
+                    diagnostics.messages.extend(plugin_messages(&output.stderr));
+                    Ok(Value::Bytes(output.value))
+                }))
+            },
+        ),
+        builtin(
+            "tikz",
+            Kind::Backend,
+            EVENTS,
+            "LaTeX document with a TikZ timetable",
+            |_, options| {
+                let tikz = tikz::Options {
+                    template_path: options.template_path.clone(),
+                    locale: options.locale,
+                };
+                Ok(events_pass::<tikz::Pass, _>(tikz, Value::Text))
+            },
+        ),
+        builtin(
+            "abstract-latex",
+            Kind::Backend,
+            EVENTS,
+            "LaTeX abstracts booklet",
+            |_, options| {
+                let abstex = abstex::Options {
+                    template_path: options.template_path.clone(),
+                    locale: options.locale,
+                    flags: options.flags.clone(),
+                };
+                Ok(events_pass::<abstex::Pass, _>(abstex, Value::Text))
+            },
+        ),
+        builtin(
+            "latexmk",
+            Kind::Backend,
+            TEXT,
+            "Compile a LaTeX document to PDF, through the cache if enabled",
+            |_, options| {
+                let latexmk = options.latexmk.clone();
+                let cache = options.cache.clone();
+                Ok(on_text(move |latex| {
+                    compiler::compile_pdf(latex, latexmk.clone(), cache.clone()).map(Value::Bytes)
+                }))
+            },
+        ),
+        builtin(
+            "html",
+            Kind::Backend,
+            EVENTS,
+            "HTML calendar",
+            |_, options| {
+                let html = HTMLBackendOptions {
+                    template_path: options.template_path.clone(),
+                    locale: options.locale,
+                    flags: options.flags.clone(),
+                };
+                Ok(events_pass::<HTMLBackend, _>(html, Value::Text))
+            },
+        ),
+        builtin(
+            "svg",
+            Kind::Backend,
+            EVENTS,
+            "SVG timetable",
+            |_, options| {
+                let svg = svg::Options {
+                    template_path: options.template_path.clone(),
+                    locale: options.locale,
+                };
+                Ok(events_pass::<svg::Pass, _>(svg, Value::Text))
+            },
+        ),
+        builtin(
+            "typst",
+            Kind::Backend,
+            EVENTS,
+            "Typst timetable",
+            |_, options| {
+                let typst = typst::Options {
+                    template_path: options.template_path.clone(),
+                    locale: options.locale,
+                };
+                Ok(events_pass::<typst::Timetable, _>(typst, Value::Text))
+            },
+        ),
+        builtin(
+            "abstract-typst",
+            Kind::Backend,
+            EVENTS,
+            "Typst abstracts booklet",
+            |_, options| {
+                let typst = typst::Options {
+                    template_path: options.template_path.clone(),
+                    locale: options.locale,
+                };
+                Ok(events_pass::<typst::Abstracts, _>(typst, Value::Text))
+            },
+        ),
+        builtin(
+            "pentabarf",
+            Kind::Backend,
+            EVENTS,
+            "Pentabarf/Frab schedule.xml",
+            |_, options| {
+                Ok(events_pass::<pentabarf::Pass, _>(
+                    options.pentabarf.clone(),
+                    Value::Text,
+                ))
+            },
+        ),
+        builtin(
+            "csv",
+            Kind::Backend,
+            EVENTS,
+            "CSV spreadsheet, one event per row",
+            |_, options| {
+                Ok(events_pass::<csv::Export, _>(
+                    options.csv.clone(),
+                    Value::Text,
+                ))
+            },
+        ),
+        builtin("seri", Kind::Backend, EVENTS, "Seri language", |_, _| {
+            Ok(events_pass::<seri::Pass, _>((), Value::Text))
+        }),
+        builtin(
+            "markdown",
+            Kind::Backend,
+            EVENTS,
+            "Markdown agenda",
+            |_, options| {
+                let agenda = agenda::Options {
+                    locale: options.locale,
+                    ..options.agenda.clone()
+                };
+                Ok(events_pass::<agenda::Markdown, _>(agenda, Value::Text))
+            },
+        ),
+        builtin(
+            "text",
+            Kind::Backend,
+            EVENTS,
+            "Plain-text agenda",
+            |_, options| {
+                let agenda = agenda::Options {
+                    locale: options.locale,
+                    ..options.agenda.clone()
+                };
+                Ok(events_pass::<agenda::Text, _>(agenda, Value::Text))
+            },
+        ),
+    ]
+}
+
+/// The built-in passes
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        for (name, entry) in builtins() {
+            registry.register(name, entry);
+        }
+        registry
+    }
+}
+
+/// A pass of a pipeline description, with its argument
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Step {
+    /// Name of the pass
+    pub name: String,
+    /// Argument of the pass, written after a colon
+    pub argument: Option<String>,
+}
+
+/// A pipeline description
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Pipeline {
+    /// The passes, the first one reading the input
+    pub steps: Vec<Step>,
+}
+
+impl FromStr for Pipeline {
+    type Err = Error;
+
+    /// Read a pipeline description. Steps are separated by `|` or new lines, and lines starting
+    /// with `#` are comments. As in a shell, a `|` in quotes or after a backslash is part of an
+    /// argument, and the quotes and backslashes are kept for the pass to read, as `plugin` does.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut lines = Vec::new();
+        for line in input
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+        {
+            lines.extend(split_steps(line)?);
+        }
+        let steps: Vec<Step> = lines
+            .into_iter()
+            .map(str::trim)
+            .filter(|step| !step.is_empty())
+            .map(|step| match step.split_once(':') {
+                Some((name, argument)) => Step {
+                    name: name.trim().to_owned(),
+                    argument: Some(argument.trim().to_owned()),
+                },
+                None => Step {
+                    name: step.to_owned(),
+                    argument: None,
+                },
+            })
+            .collect();
+        if steps.is_empty() {
+            Err(Error::Empty)
+        } else {
+            Ok(Self { steps })
+        }
+    }
+}
+
+/// Split a line of a pipeline description at the `|` that are neither quoted nor escaped, with
+/// the quoting rules of a POSIX shell
+fn split_steps(line: &str) -> Result<Vec<&str>, Error> {
+    let mut steps = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            // Single quotes do not escape anything
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (None | Some('"'), '\\') => {
+                chars.next();
+            }
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '|') => {
+                steps.push(&line[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    if quote.is_some() {
+        return Err(Error::UnclosedQuote(line.to_owned()));
+    }
+    steps.push(&line[start..]);
+    Ok(steps)
+}
+
+/// Result of a pipeline
+pub struct Output {
+    /// The output document
+    pub document: Vec<u8>,
+    /// The warnings of the analysis passes
+    pub warnings: Vec<Warning>,
+    /// What the plugins wrote to their standard error
+    pub messages: Vec<String>,
+}
+
+impl Pipeline {
+    /// Run the pipeline on an input text
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a pass is unknown, does not accept its argument or the value given by
+    /// the previous pass, or fails. The last pass must produce a document, not events.
+    pub fn run(
+        &self,
+        registry: &Registry,
+        input: &str,
+        options: &Options,
+    ) -> Result<Output, Error> {
+        // Build all the stages first, so that a typo is reported before running anything
+        let stages = self
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| {
+                let entry = registry.get(i == 0, &step.name)?;
+                let stage =
+                    (entry.build)(step.argument.as_deref(), options).map_err(|message| {
+                        Error::InvalidArgument {
+                            name: step.name.clone(),
+                            message,
+                        }
+                    })?;
+                Ok((step, entry, stage))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut value = Value::Text(input.to_owned());
+        let mut diagnostics = Diagnostics::default();
+        for (step, entry, stage) in stages {
+            if value.kind() != entry.input {
+                return Err(Error::UnexpectedValue {
+                    name: step.name.clone(),
+                    expected: entry.input,
+                    found: value.kind(),
+                });
+            }
+            value = stage(value, &mut diagnostics).map_err(|source| Error::Pass {
+                name: step.name.clone(),
+                source,
+            })?;
+        }
+
+        let Diagnostics { warnings, messages } = diagnostics;
+        match value {
+            Value::Text(text) => Ok(Output {
+                document: text.into_bytes(),
+                warnings,
+                messages,
+            }),
+            Value::Bytes(document) => Ok(Output {
+                document,
+                warnings,
+                messages,
+            }),
+            Value::Events(_) => Err(Error::UnexpectedValue {
+                name: "(end of the pipeline)".to_owned(),
+                expected: "text or bytes",
+                found: "events",
+            }),
+        }
+    }
+}
+
+#[test]
+fn test_pipeline() {
+    let pipeline =
+        Pipeline::from_str("seri | filter:!type=break\n# analysis\nlint | text").unwrap();
+    assert_eq!(pipeline.steps.len(), 4);
+    assert_eq!(pipeline.steps[1].argument.as_deref(), Some("!type=break"));
+
+    let input = "title: Welcome\ndate: 2023-04-27 10:00\nduration: 15\n---\ntitle: Coffee\ntype: break\ndate: 2023-04-27 10:15\nduration: 15";
+    let output = pipeline
+        .run(&Registry::default(), input, &Options::default())
+        .unwrap();
+    let text = String::from_utf8(output.document).unwrap();
+    assert!(text.contains("Welcome") && !text.contains("Coffee"));
+    assert_eq!(output.warnings.len(), 1);
+
+    assert!(matches!(
+        Pipeline::from_str("seri | lint").unwrap().run(
+            &Registry::default(),
+            input,
+            &Options::default()
+        ),
+        Err(Error::UnexpectedValue { .. })
+    ));
+    assert!(matches!(
+        Pipeline::from_str("seri | latexmk").unwrap().run(
+            &Registry::default(),
+            input,
+            &Options::default()
+        ),
+        Err(Error::UnexpectedValue { .. })
+    ));
+
+    // What plugins write to their standard error is returned
+    let output = Pipeline::from_str("seri | render:sh -c 'echo done >&2'")
+        .unwrap()
+        .run(&Registry::default(), input, &Options::default())
+        .unwrap();
+    assert_eq!(output.messages, ["done"]);
+
+    // Quoted or escaped `|` are part of the arguments, as in a shell
+    let pipeline =
+        Pipeline::from_str(r#"seri | render:sh -c 'echo "a | b" | cat' | render:tr a\|b b"#)
+            .unwrap();
+    assert_eq!(pipeline.steps.len(), 3);
+    assert_eq!(
+        pipeline.steps[1].argument.as_deref(),
+        Some(r#"sh -c 'echo "a | b" | cat'"#)
+    );
+    assert_eq!(pipeline.steps[2].argument.as_deref(), Some(r"tr a\|b b"));
+    assert!(matches!(
+        Pipeline::from_str("seri | render:sh -c 'echo | text"),
+        Err(Error::UnclosedQuote(_))
+    ));
+}
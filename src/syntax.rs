@@ -0,0 +1,629 @@
+//! Lexical structure of the Seri language
+//!
+//! A timetable is read line by line into [`Token`]s, which are then grouped into [`RawEvent`]s,
+//! one per event. Values are kept escaped: it is up to the consumer to [`unescape`] them or to
+//! [`split_list`] them.
+
+use thiserror::Error;
+
+/// The kind of a token
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TokenKind {
+    /// `---` alone on its line, between two events
+    Delimiter,
+    /// A `key: value` setting of the event header
+    Pair {
+        /// The unescaped key, before the first unescaped `:`
+        key: String,
+        /// The value, with continuation lines joined and quotes removed
+        value: String,
+    },
+    /// The empty line ending the event header
+    Separator,
+    /// A line of the event description
+    Text(String),
+    /// A `// comment` on its own line, without the leading slashes
+    Comment(String),
+    /// A block of lines between `/*` and `*/`, both alone on their lines, without them
+    BlockComment(String),
+    /// A fenced code block of the description, fences included, in which comments are text
+    CodeBlock(String),
+}
+
+/// A token, along with its position in the input
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Token {
+    /// The kind of token
+    pub kind: TokenKind,
+    /// The line where the token starts, starting at 1
+    pub line: usize,
+    /// The line where the token ends, included
+    pub last_line: usize,
+}
+
+/// A line of the header is not a valid `key: value` pair.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The line has no unescaped `:`
+    #[error("line {line}: `{text}` is not a valid field")]
+    InvalidField {
+        /// The line number
+        line: usize,
+        /// The line content
+        text: String,
+    },
+    /// An indented line of the header has an unescaped `:`, so it is neither a continuation nor
+    /// a setting
+    #[error("line {line}: `{text}` is indented like a continuation but has a `:`, which should be escaped as `\\:`")]
+    IndentedSetting {
+        /// The line number
+        line: usize,
+        /// The line content
+        text: String,
+    },
+    /// A quoted value is never closed
+    #[error("line {line}: unclosed quoted value")]
+    UnclosedQuote {
+        /// The line number where the quote is opened
+        line: usize,
+    },
+    /// Text follows the closing quote of a quoted value
+    #[error("line {line}: `{text}` after the closing quote")]
+    TextAfterQuote {
+        /// The line number of the closing quote
+        line: usize,
+        /// The text after the quote
+        text: String,
+    },
+    /// A block comment is never closed
+    #[error("line {line}: unclosed block comment")]
+    UnclosedComment {
+        /// The line number where the comment is opened
+        line: usize,
+    },
+}
+
+impl Error {
+    /// The line the error is located at, starting at 1
+    #[must_use]
+    pub const fn line(&self) -> usize {
+        match self {
+            Self::InvalidField { line, .. }
+            | Self::IndentedSetting { line, .. }
+            | Self::UnclosedQuote { line }
+            | Self::TextAfterQuote { line, .. }
+            | Self::UnclosedComment { line } => *line,
+        }
+    }
+}
+
+/// Whether a line delimits two events
+fn is_delimiter(line: &str) -> bool {
+    line.trim() == "---"
+}
+
+/// The text of a line comment, if the line is one
+fn line_comment(line: &str) -> Option<&str> {
+    line.trim_start().strip_prefix("//")
+}
+
+/// Whether a line opens a block comment
+fn is_block_comment_start(line: &str) -> bool {
+    line.trim() == "/*"
+}
+
+/// Whether a line closes a block comment
+fn is_block_comment_end(line: &str) -> bool {
+    line.trim() == "*/"
+}
+
+/// Whether a line would be read as something else than description text
+fn is_special(line: &str) -> bool {
+    is_delimiter(line) || line_comment(line).is_some() || is_block_comment_start(line)
+}
+
+/// The fence characters, ```` ``` ```` or `~~~`, if the line opens a code block
+pub(crate) fn code_fence(line: &str) -> Option<&'static str> {
+    let line = line.trim_start();
+    ["```", "~~~"].into_iter().find(|f| line.starts_with(f))
+}
+
+/// Whether a line closes the code block opened by `fence`
+pub(crate) fn is_code_fence_end(line: &str, fence: &str) -> bool {
+    let line = line.trim();
+    line.starts_with(fence) && line.chars().all(|c| fence.starts_with(c))
+}
+
+/// Find the first occurrence of `needle` not preceded by a backslash
+fn find_unescaped(text: &str, needle: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == needle {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// Remove the backslashes escaping characters
+#[must_use]
+pub fn unescape(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            res.extend(chars.next());
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+/// Split a list value such as `[alice, bob]` or `alice, bob` on unescaped commas, ignoring
+/// unescaped brackets
+#[must_use]
+pub fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                item.push(c);
+                item.extend(chars.next());
+            }
+            '[' | ']' => (),
+            ',' => items.push(std::mem::take(&mut item)),
+            _ => item.push(c),
+        }
+    }
+    items.push(item);
+    items
+        .iter()
+        .map(|s| unescape(s.trim()))
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Where the tokenizer is in the current event
+#[derive(PartialEq, Eq)]
+enum State {
+    /// Before the first setting
+    Start,
+    /// Reading settings
+    Header,
+    /// Reading the description
+    Description,
+}
+
+/// Read a quoted value starting on the line `start` at the given text, which starts after the
+/// opening quote. Returns the value and the index of the last line read.
+fn quoted_value(lines: &[&str], start: usize, first: &str) -> Result<(String, usize), Error> {
+    let mut value = String::new();
+    let mut text = first;
+    let mut index = start;
+    loop {
+        if let Some(end) = find_unescaped(text, '"') {
+            let rest = text[end + 1..].trim();
+            if !rest.is_empty() {
+                return Err(Error::TextAfterQuote {
+                    line: index + 1,
+                    text: rest.to_owned(),
+                });
+            }
+            value += &text[..end];
+            return Ok((value, index));
+        }
+        value += text;
+        value.push('\n');
+        index += 1;
+        text = lines
+            .get(index)
+            .ok_or(Error::UnclosedQuote { line: start + 1 })?;
+    }
+}
+
+/// The index of the last line of the code block opened by `fence` on the line `start`: its
+/// closing fence, or the last line of the event
+fn code_block_end(lines: &[&str], start: usize, fence: &str) -> usize {
+    lines[start + 1..]
+        .iter()
+        .position(|l| is_code_fence_end(l, fence) || is_delimiter(l))
+        .map_or(lines.len() - 1, |offset| {
+            let end = start + 1 + offset;
+            if is_delimiter(lines[end]) {
+                end - 1
+            } else {
+                end
+            }
+        })
+}
+
+/// Split a timetable into tokens
+///
+/// # Errors
+///
+/// Returns an [`Error`] if a header line is not a valid `key: value` pair.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, Error> {
+    let lines: Vec<&str> = input.lines().collect();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut state = State::Start;
+    let mut index = 0;
+
+    while index < lines.len() {
+        let line = lines[index];
+        let line_number = index + 1;
+        let token = |kind, last: usize| Token {
+            kind,
+            line: line_number,
+            last_line: last + 1,
+        };
+
+        if is_delimiter(line) {
+            tokens.push(token(TokenKind::Delimiter, index));
+            state = State::Start;
+        } else if let Some(comment) = line_comment(line) {
+            tokens.push(token(TokenKind::Comment(comment.to_owned()), index));
+        } else if is_block_comment_start(line) {
+            let end = lines[index + 1..]
+                .iter()
+                .position(|l| is_block_comment_end(l))
+                .map(|offset| index + 1 + offset)
+                .ok_or(Error::UnclosedComment { line: line_number })?;
+            let comment = lines[index + 1..end].join("\n");
+            tokens.push(token(TokenKind::BlockComment(comment), end));
+            index = end;
+        } else if let Some(fence) = code_fence(line).filter(|_| state == State::Description) {
+            let end = code_block_end(&lines, index, fence);
+            let code = lines[index..=end]
+                .iter()
+                .map(|l| {
+                    l.strip_prefix('\\')
+                        .filter(|rest| is_delimiter(rest))
+                        .unwrap_or(l)
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            tokens.push(token(TokenKind::CodeBlock(code), end));
+            index = end;
+        } else if state == State::Description {
+            // A leading backslash protects a line that would be read as a delimiter or a comment
+            let text = line
+                .strip_prefix('\\')
+                .filter(|rest| is_special(rest))
+                .unwrap_or(line);
+            tokens.push(token(TokenKind::Text(text.to_owned()), index));
+        } else if line.trim().is_empty() {
+            if state == State::Header {
+                tokens.push(token(TokenKind::Separator, index));
+                state = State::Description;
+            }
+        } else if state == State::Header && line.starts_with([' ', '\t']) {
+            // Indented lines continue the previous value, comments may be in between. A `:` would
+            // make it ambiguous with a setting.
+            if find_unescaped(line, ':').is_some() {
+                return Err(Error::IndentedSetting {
+                    line: line_number,
+                    text: line.trim().to_owned(),
+                });
+            }
+            let previous = tokens
+                .iter_mut()
+                .rev()
+                .find(|t| !matches!(t.kind, TokenKind::Comment(_) | TokenKind::BlockComment(_)));
+            if let Some(Token {
+                kind: TokenKind::Pair { value, .. },
+                last_line,
+                ..
+            }) = previous
+            {
+                if !value.is_empty() {
+                    value.push(' ');
+                }
+                *value += line.trim();
+                *last_line = line_number;
+            }
+        } else {
+            let colon = find_unescaped(line, ':').ok_or_else(|| Error::InvalidField {
+                line: line_number,
+                text: line.to_owned(),
+            })?;
+            let key = unescape(line[..colon].trim());
+            let rest = line[colon + 1..].trim_start();
+            let (value, last) = if let Some(quoted) = rest.strip_prefix('"') {
+                quoted_value(&lines, index, quoted)?
+            } else {
+                (rest.trim_end().to_owned(), index)
+            };
+            tokens.push(token(TokenKind::Pair { key, value }, last));
+            state = State::Header;
+            index = last;
+        }
+        index += 1;
+    }
+
+    Ok(tokens)
+}
+
+/// A setting of an event
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Setting {
+    /// The key of the setting
+    pub key: String,
+    /// The escaped value of the setting
+    pub value: String,
+    /// The line where the setting is, starting at 1
+    pub line: usize,
+}
+
+/// The settings and description of an event, before interpretation
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct RawEvent {
+    /// The line where the event starts, starting at 1
+    pub line: usize,
+    /// The line where the event ends, included
+    pub last_line: usize,
+    /// The settings of the event, in order
+    pub settings: Vec<Setting>,
+    /// The description of the event, if not empty
+    pub description: Option<String>,
+}
+
+impl RawEvent {
+    /// Get the last setting with the given key
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&Setting> {
+        self.settings.iter().rev().find(|s| s.key == key)
+    }
+}
+
+/// Group tokens into events, skipping empty ones
+#[must_use]
+pub fn group(tokens: &[Token]) -> Vec<RawEvent> {
+    let mut events = Vec::new();
+    for chunk in tokens.split(|t| t.kind == TokenKind::Delimiter) {
+        // Comments are not part of any event, so that a commented out event disappears
+        let chunk: Vec<&Token> = chunk
+            .iter()
+            .filter(|t| !matches!(t.kind, TokenKind::Comment(_) | TokenKind::BlockComment(_)))
+            .collect();
+        let (Some(first), Some(last)) = (chunk.first(), chunk.last()) else {
+            continue;
+        };
+        let mut event = RawEvent {
+            line: first.line,
+            last_line: last.last_line,
+            ..RawEvent::default()
+        };
+        let mut description = Vec::new();
+        for token in &chunk {
+            match &token.kind {
+                TokenKind::Pair { key, value } => event.settings.push(Setting {
+                    key: key.clone(),
+                    value: value.clone(),
+                    line: token.line,
+                }),
+                TokenKind::Text(text) | TokenKind::CodeBlock(text) => {
+                    description.push(text.as_str());
+                }
+                TokenKind::Delimiter
+                | TokenKind::Separator
+                | TokenKind::Comment(_)
+                | TokenKind::BlockComment(_) => (),
+            }
+        }
+        let description = description.join("\n");
+        let description = description.trim();
+        event.description = (!description.is_empty()).then(|| description.to_owned());
+        events.push(event);
+    }
+    events
+}
+
+/// Write a setting, starting at the given line, as `key: value` with its continuation lines
+/// indented by two spaces
+///
+/// Lines in `comments` are comments within the setting, that are kept as is.
+fn format_pair(lines: &[&str], token: &Token, comments: &[bool], out: &mut Vec<String>) {
+    let first = lines[token.line - 1];
+    let colon = find_unescaped(first, ':').unwrap_or(first.len());
+    let value = first.get(colon + 1..).unwrap_or_default().trim_start();
+    let quoted = value.starts_with('"');
+    // Spaces at the end of a quoted value spanning several lines are part of it
+    let value = if quoted && token.last_line > token.line {
+        value
+    } else {
+        value.trim_end()
+    };
+    let key = first[..colon].trim();
+    out.push(if value.is_empty() {
+        format!("{key}:")
+    } else {
+        format!("{key}: {value}")
+    });
+
+    for (index, line) in lines
+        .iter()
+        .enumerate()
+        .take(token.last_line)
+        .skip(token.line)
+    {
+        out.push(if quoted || comments[index] {
+            (*line).to_owned()
+        } else {
+            format!("  {}", line.trim())
+        });
+    }
+}
+
+/// Format a timetable
+///
+/// Settings are written as `key: value` with continuation lines indented by two spaces, a single
+/// empty line separates the header from the description and the events, and empty lines around
+/// descriptions are removed. Descriptions and comments are kept as is.
+///
+/// # Errors
+///
+/// Returns an [`Error`] if the timetable cannot be tokenized.
+pub fn format(input: &str) -> Result<String, Error> {
+    let lines: Vec<&str> = input.lines().collect();
+    let tokens = tokenize(input)?;
+    let mut comments = vec![false; lines.len()];
+    for token in &tokens {
+        if matches!(
+            token.kind,
+            TokenKind::Comment(_) | TokenKind::BlockComment(_)
+        ) {
+            comments[token.line - 1..token.last_line].fill(true);
+        }
+    }
+
+    let mut out: Vec<String> = Vec::new();
+    let trim_end = |out: &mut Vec<String>| {
+        while out.last().is_some_and(String::is_empty) {
+            out.pop();
+        }
+    };
+    let mut pending_separator = false;
+    // Last line written by a setting, with the comments inside it
+    let mut written = 0;
+    for token in &tokens {
+        if token.line <= written {
+            continue;
+        }
+        match &token.kind {
+            TokenKind::Delimiter => {
+                trim_end(&mut out);
+                if !out.is_empty() {
+                    out.push(String::new());
+                }
+                out.push("---".to_owned());
+                pending_separator = false;
+            }
+            TokenKind::Pair { .. } => {
+                format_pair(&lines, token, &comments, &mut out);
+                written = token.last_line;
+            }
+            TokenKind::Separator => pending_separator = true,
+            TokenKind::Text(text) if pending_separator && text.trim().is_empty() => (),
+            TokenKind::Text(_)
+            | TokenKind::Comment(_)
+            | TokenKind::BlockComment(_)
+            | TokenKind::CodeBlock(_) => {
+                if std::mem::take(&mut pending_separator) {
+                    out.push(String::new());
+                }
+                let text = &lines[token.line - 1..token.last_line];
+                if let TokenKind::Comment(_) = token.kind {
+                    out.push(text[0].trim().to_owned());
+                } else {
+                    out.extend(text.iter().map(|l| (*l).to_owned()));
+                }
+            }
+        }
+    }
+    trim_end(&mut out);
+    Ok(out.into_iter().map(|l| l + "\n").collect())
+}
+
+#[test]
+fn test_comments() {
+    let input = "// TODO confirm with speaker\ntitle: a\n// a note\n  continued\n\n\\// not a comment\n---\n/*\ntitle: disabled\n---\n*/\n";
+    let tokens = tokenize(input).unwrap();
+    assert_eq!(
+        tokens[0].kind,
+        TokenKind::Comment(" TODO confirm with speaker".to_owned())
+    );
+    let events = group(&tokens);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].get("title").unwrap().value, "a continued");
+    assert_eq!(events[0].description.as_deref(), Some("// not a comment"));
+    assert_eq!(tokens.last().unwrap().line, 8);
+    assert_eq!(tokens.last().unwrap().last_line, 11);
+
+    assert_eq!(tokenize("/*\ntitle: a").unwrap_err().line(), 1);
+
+    // Comments are text in code blocks, which end with their fence or the event
+    let input = "title: a\n\n```c\n// a comment\n/*\n```rust\n```\n// note\n~~~\n// code\n\\---\n---\ntitle: b";
+    let tokens = tokenize(input).unwrap();
+    assert_eq!(
+        tokens[2],
+        Token {
+            kind: TokenKind::CodeBlock("```c\n// a comment\n/*\n```rust\n```".to_owned()),
+            line: 3,
+            last_line: 7,
+        }
+    );
+    assert_eq!(tokens[3].kind, TokenKind::Comment(" note".to_owned()));
+    let events = group(&tokens);
+    assert_eq!(
+        events[0].description.as_deref(),
+        Some("```c\n// a comment\n/*\n```rust\n```\n~~~\n// code\n---")
+    );
+    assert_eq!(events[1].line, 13);
+}
+
+#[test]
+fn test_tokenize() {
+    let input = "title: \"A long\n title\"\nspeakers: alice,\n  bob\\, jr\n\nSome text\n\\---\nmore\n---\ntitle: Other\\: talk";
+    let events = group(&tokenize(input).unwrap());
+
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].get("title").unwrap().value, "A long\n title");
+    assert_eq!(
+        split_list(&events[0].get("speakers").unwrap().value),
+        vec!["alice", "bob, jr"]
+    );
+    assert_eq!(
+        events[0].description.as_deref(),
+        Some("Some text\n---\nmore")
+    );
+    assert_eq!(events[1].line, 10);
+    assert_eq!(
+        unescape(&events[1].get("title").unwrap().value),
+        "Other: talk"
+    );
+
+    assert_eq!(tokenize("title foo").unwrap_err().line(), 1);
+    assert!(matches!(
+        tokenize("title: a\n  room: b"),
+        Err(Error::IndentedSetting { line: 2, .. })
+    ));
+    assert_eq!(
+        group(&tokenize("title: a\n  see\\: b").unwrap())[0].settings[0].value,
+        "a see\\: b"
+    );
+    assert!(matches!(
+        tokenize("title: \"a\n b\" c"),
+        Err(Error::TextAfterQuote { line: 2, .. })
+    ));
+}
+
+#[test]
+fn test_format() {
+    let input = "\n  title :Welcome  \nspeakers: alice,\n// a note\n\t  bob\nabstract: \"Two  \n lines\"\n\n\nSome text  \n\n\n---\n\ntitle: Coffee\n\n---\n  // served outside\ntitle: Lunch";
+    let formatted = format(input).unwrap();
+    assert_eq!(
+        formatted,
+        "title: Welcome\nspeakers: alice,\n// a note\n  bob\nabstract: \"Two  \n lines\"\n\nSome text  \n\n---\ntitle: Coffee\n\n---\n// served outside\ntitle: Lunch\n"
+    );
+    assert_eq!(format(&formatted).unwrap(), formatted);
+
+    // Only the lines change
+    let content = |input: &str| {
+        group(&tokenize(input).unwrap())
+            .into_iter()
+            .map(|e| {
+                let settings: Vec<(String, String)> =
+                    e.settings.into_iter().map(|s| (s.key, s.value)).collect();
+                (settings, e.description)
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(content(&formatted), content(input));
+}
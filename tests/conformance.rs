@@ -0,0 +1,425 @@
+//! Conformance of the editor grammars with the parser of the compiler
+//!
+//! The timetables of `spec/corpus` are read by the parser, which gives:
+//! - the syntax tree expected from the tree-sitter grammar, written to the corpus of
+//!   `editors/tree-sitter-seri`, so that `tree-sitter test` compares the grammar with the parser,
+//! - the kind of each line, compared with the scopes given to it by the TextMate grammar.
+//!
+//! Run with `SERI_UPDATE_CORPUS=1` to write the tree-sitter corpus after changing the timetables.
+
+use std::{
+    collections::HashMap,
+    env,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use fancy_regex::{Captures, Regex};
+use serde_json::Value;
+use seri::{
+    passes::{parser::ParseTimetable, CompilingPass},
+    syntax::{self, Token, TokenKind},
+};
+
+/// A path in the repository
+fn path(relative: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(relative)
+}
+
+/// The timetables of the corpus, by name
+fn corpus() -> Vec<(String, String)> {
+    let mut files: Vec<PathBuf> = fs::read_dir(path("spec/corpus"))
+        .expect("the corpus exists")
+        .map(|entry| entry.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "seri"))
+        .collect();
+    files.sort();
+    files
+        .iter()
+        .map(|p| {
+            let name = p.file_stem().unwrap().to_string_lossy().into_owned();
+            let input = fs::read_to_string(p).unwrap();
+            // Every timetable of the corpus is valid
+            syntax::tokenize(&input).unwrap_or_else(|e| panic!("{name}: {e}"));
+            ParseTimetable::apply(&input).unwrap_or_else(|e| panic!("{name}: {e}"));
+            (name, input)
+        })
+        .collect()
+}
+
+fn is_comment(token: &Token) -> bool {
+    matches!(
+        token.kind,
+        TokenKind::Comment(_) | TokenKind::BlockComment(_)
+    )
+}
+
+/// The tree-sitter node of a comment or description token
+fn node(token: &Token) -> &'static str {
+    match token.kind {
+        TokenKind::Comment(_) => "(comment)",
+        TokenKind::BlockComment(_) => "(block_comment)",
+        TokenKind::Text(_) => "(text)",
+        TokenKind::CodeBlock(_) => "(code_block)",
+        _ => unreachable!("not a comment nor a description line"),
+    }
+}
+
+/// The text after the first unescaped `:` of a line
+fn after_colon(line: &str) -> &str {
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == ':' {
+            return &line[i + 1..];
+        }
+    }
+    ""
+}
+
+/// The syntax tree of a timetable, as given by the tree-sitter grammar
+fn syntax_tree(input: &str) -> String {
+    let lines: Vec<&str> = input.lines().collect();
+    let tokens = syntax::tokenize(input).unwrap();
+    let mut tree = String::from("(timetable");
+    for (i, section) in tokens.split(|t| t.kind == TokenKind::Delimiter).enumerate() {
+        if i > 0 {
+            tree += "\n  (delimiter)";
+        }
+        // Comments before the first setting are outside of the event
+        let start = section
+            .iter()
+            .position(|t| !is_comment(t))
+            .unwrap_or(section.len());
+        for token in &section[..start] {
+            let _ = write!(tree, "\n  {}", node(token));
+        }
+        let event = &section[start..];
+        if event.is_empty() {
+            continue;
+        }
+
+        tree += "\n  (event";
+        let separator = event
+            .iter()
+            .position(|t| t.kind == TokenKind::Separator)
+            .unwrap_or(event.len());
+        for (j, token) in event[..separator].iter().enumerate() {
+            let TokenKind::Pair { key, value } = &token.kind else {
+                continue;
+            };
+            tree += "\n    (setting";
+            if !key.is_empty() {
+                tree += " (key)";
+            }
+            if !after_colon(lines[token.line - 1]).trim().is_empty() {
+                tree += " (value)";
+            }
+            // Continuation lines and comments, up to the next setting
+            let comments: Vec<&Token> = event[j + 1..separator]
+                .iter()
+                .take_while(|t| is_comment(t))
+                .collect();
+            let value_end = token.line + value.matches('\n').count();
+            let mut children: Vec<(usize, &str)> = (value_end + 1..=token.last_line)
+                .filter(|l| !comments.iter().any(|c| (c.line..=c.last_line).contains(l)))
+                .map(|l| (l, "(continuation)"))
+                .collect();
+            children.extend(comments.iter().map(|c| (c.line, node(c))));
+            children.sort_unstable();
+            for (_, child) in children {
+                let _ = write!(tree, " {child}");
+            }
+            tree += ")";
+        }
+        if let Some(description) = event.get(separator + 1..).filter(|d| !d.is_empty()) {
+            tree += "\n    (description";
+            for token in description {
+                // Blank lines are hidden
+                if !matches!(token.kind, TokenKind::Text(_))
+                    || !lines[token.line - 1].trim().is_empty()
+                {
+                    let _ = write!(tree, " {}", node(token));
+                }
+            }
+            tree += ")";
+        }
+        tree += ")";
+    }
+    tree + ")"
+}
+
+#[test]
+fn test_tree_sitter_corpus() {
+    // Suffixed separators, since timetables have `---` lines
+    let expected = corpus()
+        .into_iter()
+        .map(|(name, input)| {
+            format!(
+                "{0}|||\n{name}\n{0}|||\n\n{input}\n{1}|||\n\n{2}\n",
+                "=".repeat(80),
+                "-".repeat(80),
+                syntax_tree(&input)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    let corpus = path("editors/tree-sitter-seri/test/corpus/conformance.txt");
+    if env::var_os("SERI_UPDATE_CORPUS").is_some() {
+        fs::write(&corpus, &expected).unwrap();
+    }
+    assert!(
+        fs::read_to_string(&corpus).is_ok_and(|c| c == expected),
+        "the tree-sitter corpus does not match the parser, run with SERI_UPDATE_CORPUS=1 to update it"
+    );
+}
+
+/// How a line is read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line {
+    /// An empty line outside of descriptions
+    Blank,
+    Delimiter,
+    /// A line of a line or block comment
+    Comment,
+    /// The first line of a setting
+    Setting,
+    /// A line continuing the value of a setting
+    Value,
+    Description,
+}
+
+/// How each line of a timetable is read by the parser
+fn parsed_lines(input: &str) -> Vec<Line> {
+    let mut lines = vec![Line::Blank; input.lines().count()];
+    for token in syntax::tokenize(input).unwrap() {
+        let first = token.line - 1;
+        match token.kind {
+            TokenKind::Delimiter => lines[first] = Line::Delimiter,
+            // Comments within a setting come after it
+            TokenKind::Comment(_) | TokenKind::BlockComment(_) => {
+                lines[first..token.last_line].fill(Line::Comment);
+            }
+            TokenKind::Pair { .. } => {
+                lines[first] = Line::Setting;
+                lines[first + 1..token.last_line].fill(Line::Value);
+            }
+            TokenKind::Separator => (),
+            TokenKind::Text(_) => lines[first] = Line::Description,
+            TokenKind::CodeBlock(_) => lines[first..token.last_line].fill(Line::Description),
+        }
+    }
+    lines
+}
+
+/// A TextMate grammar, applied line by line like editors do
+///
+/// Only what the Seri grammar uses is supported: `match` and `begin`/`end` rules, their
+/// captures, `contentName` and includes from the repository.
+struct TextMate {
+    grammar: Value,
+    regexes: HashMap<String, Regex>,
+}
+
+/// The scopes given by a rule, for the given key such as `name`
+fn scopes(rule: &Value, key: &str) -> Vec<String> {
+    rule[key]
+        .as_str()
+        .map(|s| s.split_whitespace().map(str::to_owned).collect())
+        .unwrap_or_default()
+}
+
+/// The scopes given by captures to the character at the given index
+fn capture_scopes(captures: &Value, matched: &Captures, index: usize) -> Vec<String> {
+    let Some(captures) = captures.as_object() else {
+        return Vec::new();
+    };
+    captures
+        .iter()
+        .filter(|(group, _)| {
+            group
+                .parse()
+                .ok()
+                .and_then(|g| matched.get(g))
+                .is_some_and(|m| (m.start()..m.end()).contains(&index))
+        })
+        .flat_map(|(_, capture)| scopes(capture, "name"))
+        .collect()
+}
+
+/// The rules of a list of patterns of a grammar, with includes replaced
+fn rules<'a>(grammar: &'a Value, patterns: &'a Value, rules: &mut Vec<&'a Value>) {
+    for pattern in patterns.as_array().into_iter().flatten() {
+        let rule = match pattern["include"].as_str() {
+            Some(include) => {
+                let name = include
+                    .strip_prefix('#')
+                    .expect("only includes the repository");
+                &grammar["repository"][name]
+            }
+            None => pattern,
+        };
+        if rule.get("match").is_some() || rule.get("begin").is_some() {
+            rules.push(rule);
+        } else {
+            self::rules(grammar, &rule["patterns"], rules);
+        }
+    }
+}
+
+impl TextMate {
+    fn read(path: &Path) -> Self {
+        Self {
+            grammar: serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap(),
+            regexes: HashMap::new(),
+        }
+    }
+
+    /// The first match of a regex in a line, from the given index
+    fn find<'t>(&mut self, regex: &str, line: &'t str, from: usize) -> Option<Captures<'t>> {
+        self.regexes
+            .entry(regex.to_owned())
+            .or_insert_with(|| Regex::new(regex).unwrap())
+            .captures_from_pos(line, from)
+            .unwrap()
+    }
+
+    /// The scopes of each line: before the line, and at its first character that is not blank
+    fn scopes(&mut self, input: &str) -> Vec<(Vec<String>, Option<Vec<String>>)> {
+        let grammar = self.grammar.clone();
+        let mut stack: Vec<&Value> = Vec::new();
+        let stack_scopes = |stack: &[&Value]| -> Vec<String> {
+            stack
+                .iter()
+                .flat_map(|r| [scopes(r, "name"), scopes(r, "contentName")].concat())
+                .collect()
+        };
+
+        let mut result = Vec::new();
+        for line in input.lines() {
+            let before = stack_scopes(&stack);
+            let first = line.find(|c: char| !c.is_whitespace());
+            let mut at_first = None;
+            let mut index = 0;
+            let mut empty_matches = 0;
+            loop {
+                // The end of the current rule wins over its patterns matching at the same index
+                let mut candidates = Vec::new();
+                let patterns = match stack.last() {
+                    Some(rule) => {
+                        candidates.push((*rule, "end"));
+                        &rule["patterns"]
+                    }
+                    None => &grammar["patterns"],
+                };
+                let mut found_rules = Vec::new();
+                rules(&grammar, patterns, &mut found_rules);
+                for rule in found_rules {
+                    let key = if rule.get("match").is_some() {
+                        "match"
+                    } else {
+                        "begin"
+                    };
+                    candidates.push((rule, key));
+                }
+                let found = candidates
+                    .into_iter()
+                    .filter_map(|(rule, key)| {
+                        let matched = self.find(rule[key].as_str()?, line, index)?;
+                        Some((rule, key, matched))
+                    })
+                    .min_by_key(|(_, _, m)| m.get(0).unwrap().start());
+
+                let Some((rule, key, matched)) = found else {
+                    if first.is_some_and(|f| f >= index) {
+                        at_first = at_first.or_else(|| Some(stack_scopes(&stack)));
+                    }
+                    break;
+                };
+                let (start, end) = {
+                    let m = matched.get(0).unwrap();
+                    (m.start(), m.end())
+                };
+                if first.is_some_and(|f| (index..start).contains(&f)) {
+                    at_first = Some(stack_scopes(&stack));
+                }
+                let captures = match key {
+                    "end" => {
+                        stack.pop();
+                        &rule["endCaptures"]
+                    }
+                    "begin" => &rule["beginCaptures"],
+                    _ => &rule["captures"],
+                };
+                if let Some(f) = first.filter(|f| (start..end).contains(f)) {
+                    let mut s = stack_scopes(&stack);
+                    s.extend(scopes(rule, "name"));
+                    s.extend(capture_scopes(captures, &matched, f));
+                    at_first = Some(s);
+                }
+                if key == "begin" {
+                    stack.push(rule);
+                }
+
+                empty_matches = if start == end { empty_matches + 1 } else { 0 };
+                assert!(empty_matches < 8, "the grammar loops on `{line}`");
+                index = end;
+            }
+            result.push((before, at_first));
+        }
+        result
+    }
+
+    /// How each line of a timetable is highlighted
+    fn lines(&mut self, input: &str) -> Vec<Option<Line>> {
+        let has = |scopes: &[String], prefix: &str| scopes.iter().any(|s| s.starts_with(prefix));
+        self.scopes(input)
+            .into_iter()
+            .map(|(before, first)| match first {
+                // Empty lines are in the scopes they are in
+                None if has(&before, "comment") => Some(Line::Comment),
+                None if has(&before, "string") => Some(Line::Value),
+                None if has(&before, "meta.description") => Some(Line::Description),
+                None => Some(Line::Blank),
+                Some(s) if has(&s, "comment") => Some(Line::Comment),
+                Some(s) if has(&s, "meta.separator") => Some(Line::Delimiter),
+                Some(s)
+                    if has(&s, "support.type.property-name")
+                        || has(&s, "punctuation.separator.key-value") =>
+                {
+                    Some(Line::Setting)
+                }
+                Some(s) if has(&s, "meta.description") => Some(Line::Description),
+                Some(s) if has(&s, "string") || has(&s, "punctuation.definition.string") => {
+                    Some(Line::Value)
+                }
+                Some(_) => None,
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_textmate_grammar() {
+    let mut grammar = TextMate::read(&path("editors/vscode/syntaxes/seri.tmLanguage.json"));
+    for (name, input) in corpus() {
+        let lines = input.lines();
+        let parsed = parsed_lines(&input);
+        let highlighted = grammar.lines(&input);
+        for (number, ((text, parsed), highlighted)) in
+            lines.zip(parsed).zip(highlighted).enumerate()
+        {
+            assert_eq!(
+                highlighted,
+                Some(parsed),
+                "{name}.seri:{}: `{text}`",
+                number + 1
+            );
+        }
+    }
+}